@@ -1,103 +0,0 @@
-const DEFAULT_STATS = {
-  crates_opened: 0.0,
-  chests_opened: 0.0,
-  idols_grabbed: 0.0,
-  idols_converted: 0.0,
-  damsels_grabbed: 0.0,
-  kisses_bought: 0.0,
-  damsels_bought: 0.0,
-  damsels_saved: 0.0,
-  damsels_killed: 0.0,
-  items_bought: 0.0,
-  items_stolen: 0.0,
-  dice_games_played: 0.0,
-  dice_games_won: 0.0,
-  dice_games_lost: 0.0,
-  level_deaths: {
-    level_1: 0.0,
-    level_2: 0.0,
-    level_3: 0.0,
-    level_4: 0.0,
-    level_5: 0.0,
-    level_6: 0.0,
-    level_7: 0.0,
-    level_8: 0.0,
-    level_9: 0.0,
-    level_10: 0.0,
-    level_11: 0.0,
-    level_12: 0.0,
-    level_13: 0.0,
-    level_14: 0.0,
-    level_15: 0.0,
-    level_16: 0.0,
-  },
-  enemy_deaths: {
-    bat: 0.0,
-    snake: 0.0,
-    spider: 0.0,
-    giant_spider: 0.0,
-    caveman: 0.0,
-    skeleton: 0.0,
-    zombie: 0.0,
-    vampire: 0.0,
-    frog: 0.0,
-    fire_frog: 0.0,
-    mantrap: 0.0,
-    piranha: 0.0,
-    megamouth: 0.0,
-    yeti: 0.0,
-    yeti_king: 0.0,
-    alien: 0.0,
-    ufo: 0.0,
-    alien_boss: 0.0,
-    hawkman: 0.0,
-    shopkeeper: 0.0,
-    tomb_lord: 0.0,
-    magma_man: 0.0,
-    olmec: 0.0,
-    ghost: 0.0,
-  },
-  misc_deaths: {
-    rock: 0.0,
-    explosion: 0.0,
-    crushed: 0.0,
-    long_fall: 0.0,
-    spikes: 0.0,
-    boulder: 0.0,
-    arrow_trap: 0.0,
-    spear_trap: 0.0,
-    smash_trap: 0.0,
-    ceiling_trap: 0.0,
-    pit: 0.0,
-    lava: 0.0,
-  },
-  enemy_kills: {
-    bat: 0.0,
-    snake: 0.0,
-    spider: 0.0,
-    giant_spider: 0.0,
-    caveman: 0.0,
-    skeleton: 0.0,
-    zombie: 0.0,
-    vampire: 0.0,
-    frog: 0.0,
-    monkey: 0.0,
-    fire_frog: 0.0,
-    mantrap: 0.0,
-    piranha: 0.0,
-    megamouth: 0.0,
-    yeti: 0.0,
-    yeti_king: 0.0,
-    alien: 0.0,
-    ufo: 0.0,
-    alien_boss: 0.0,
-    hawkman: 0.0,
-    shopkeeper: 0.0,
-    tomb_lord: 0.0,
-    olmec: 0.0,
-  },
-};
-
-export default {
-  DEFAULT_STATS,
-};
@@ -1,8 +1,25 @@
 import { writable, readable } from "svelte/store";
-import constants from "./constants";
 
-const stats = writable({ ...constants.DEFAULT_STATS });
+// Zeroed stats until the tracker describes its counters at /stats/catalog.
+let defaultStats = {
+  level_deaths: {},
+  enemy_deaths: {},
+  misc_deaths: {},
+  enemy_kills: {},
+};
+
+const stats = writable({ ...defaultStats });
+let haveStats = false;
 const connected = writable(false);
+const status = writable(null);
+
+// Attach states broadcast by the tracker before stats are readable.
+const WAITING_STATES = [
+  "Searching",
+  "ProcessFound",
+  "WaitingForGameData",
+  "ProcessExited",
+];
 
 function get_ruleset() {
   const params = new URLSearchParams(window.location.search);
@@ -16,6 +33,30 @@ function get_ruleset() {
 
 const ruleset = get_ruleset();
 
+// Build zeroed stats shaped like the tracker's payload from its catalog.
+function zeroStats(catalog) {
+  const zeroed = {};
+  for (const stat of catalog) {
+    const [collection, name] = stat.key.split(".");
+    if (name === undefined) {
+      zeroed[collection] = 0.0;
+    } else {
+      zeroed[collection] = { ...zeroed[collection], [name]: 0.0 };
+    }
+  }
+  return zeroed;
+}
+
+fetch("/stats/catalog")
+  .then((response) => response.json())
+  .then((catalog) => {
+    defaultStats = zeroStats(catalog);
+    if (!haveStats) {
+      stats.set({ ...defaultStats });
+    }
+  })
+  .catch((err) => console.error("Failed to load the stat catalog: ", err));
+
 let ws = null;
 
 function connect() {
@@ -23,11 +64,16 @@ function connect() {
 
   ws.onmessage = function (event) {
     const data = JSON.parse(event.data);
-    if (data.type == "Connecting" && connected) {
+    if (WAITING_STATES.includes(data.type)) {
       connected.set(false);
-      stats.set({ ...constants.DEFAULT_STATS });
+      status.set(data.reason);
+      stats.set({ ...defaultStats });
+      haveStats = false;
+    } else if (data.type == "Attached") {
+      status.set(data.reason);
     } else if (data.type == "Payload") {
       stats.set(data.stats);
+      haveStats = true;
       connected.set(true);
     }
   };
@@ -50,5 +96,6 @@ connect();
 export default {
   stats,
   connected,
+  status,
   ruleset,
 };
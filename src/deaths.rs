@@ -0,0 +1,266 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::mem_reader::unix_millis;
+use crate::stats::StatsDelta;
+
+/// How many deaths `DeathTracker` remembers.
+pub const HISTORY_LEN: usize = 10;
+
+/// How long to wait for the other half of a death before reporting what was seen.
+/// The game normally bumps both counters at once, but a read can land in between.
+const PAIR_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The four areas of the game, four levels each.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum Area {
+    Mines,
+    Jungle,
+    IceCaves,
+    Temple,
+}
+
+impl Area {
+    /// The area containing `level`, counting levels 1 to 16 across the whole game.
+    pub fn of_level(level: u32) -> Option<Area> {
+        match level {
+            1..=4 => Some(Area::Mines),
+            5..=8 => Some(Area::Jungle),
+            9..=12 => Some(Area::IceCaves),
+            13..=16 => Some(Area::Temple),
+            _ => None,
+        }
+    }
+}
+
+/// Name `level`, counted from 1 to 16 across the whole game, as the game shows
+/// it, e.g. `"3-2"` for level 10.
+pub fn level_name(level: u32) -> Option<String> {
+    Area::of_level(level)?;
+    Some(format!("{}-{}", (level - 1) / 4 + 1, (level - 1) % 4 + 1))
+}
+
+/// A death, put together from the level and cause counters that went up with it.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct DeathEvent {
+    /// Level number from 1 to 16, or `None` when no level counter went up.
+    pub level: Option<u32>,
+    pub area: Option<Area>,
+    /// Dotted key of the cause counter, e.g. `"enemy_deaths.spider"`, or `None`
+    /// when no cause counter went up.
+    pub cause: Option<&'static str>,
+    /// Milliseconds since the Unix epoch when the death was first seen.
+    pub timestamp: u64,
+}
+
+impl DeathEvent {
+    /// Short description for logs, e.g. `"2-3 spider"`.
+    pub fn summary(&self) -> String {
+        let level = self
+            .level
+            .and_then(level_name)
+            .unwrap_or_else(|| "?".to_string());
+        let cause = self
+            .cause
+            .and_then(|key| key.split('.').nth(1))
+            .unwrap_or("unknown");
+        format!("{} {}", level, cause.replace('_', " "))
+    }
+}
+
+/// Pairs level and cause counter increments into deaths, and keeps the last few.
+#[derive(Debug, Default)]
+pub struct DeathTracker {
+    level: Option<u32>,
+    cause: Option<&'static str>,
+    /// When the half-seen death in `level`/`cause` was first seen.
+    since: Option<(Instant, u64)>,
+    history: VecDeque<DeathEvent>,
+}
+
+impl DeathTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The most recent deaths, oldest first.
+    pub fn history(&self) -> Vec<DeathEvent> {
+        self.history.iter().cloned().collect()
+    }
+
+    /// Feed the counters that changed since the last read, and get back the deaths
+    /// that are complete. Call this on every read, even when nothing changed, so a
+    /// half-seen death is reported once it times out.
+    pub fn update(&mut self, delta: &StatsDelta) -> Vec<DeathEvent> {
+        self.update_at(delta, Instant::now())
+    }
+
+    fn update_at(&mut self, delta: &StatsDelta, now: Instant) -> Vec<DeathEvent> {
+        let mut deaths = Vec::new();
+        for change in delta
+            .changes
+            .iter()
+            .filter(|change| change.new > change.old)
+        {
+            if let Some(level) = change
+                .key
+                .strip_prefix("level_deaths.level_")
+                .and_then(|level| level.parse().ok())
+            {
+                if self.level.is_some() {
+                    deaths.extend(self.flush());
+                }
+                self.level = Some(level);
+            } else if change.key.starts_with("enemy_deaths.")
+                || change.key.starts_with("misc_deaths.")
+            {
+                if self.cause.is_some() {
+                    deaths.extend(self.flush());
+                }
+                self.cause = Some(change.key);
+            } else {
+                continue;
+            }
+            self.since.get_or_insert_with(|| (now, unix_millis()));
+        }
+
+        let timed_out = self
+            .since
+            .is_some_and(|(since, _)| now - since > PAIR_TIMEOUT);
+        if (self.level.is_some() && self.cause.is_some()) || timed_out {
+            deaths.extend(self.flush());
+        }
+        deaths
+    }
+
+    /// Report the pending death, if any, and remember it.
+    fn flush(&mut self) -> Option<DeathEvent> {
+        let (_, timestamp) = self.since.take()?;
+        let level = self.level.take();
+        let death = DeathEvent {
+            level,
+            area: level.and_then(Area::of_level),
+            cause: self.cause.take(),
+            timestamp,
+        };
+        if self.history.len() == HISTORY_LEN {
+            self.history.pop_front();
+        }
+        self.history.push_back(death.clone());
+        Some(death)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::StatChange;
+
+    fn delta(keys: &[&'static str]) -> StatsDelta {
+        StatsDelta {
+            changes: keys
+                .iter()
+                .map(|&key| StatChange {
+                    key,
+                    old: 0.0,
+                    new: 1.0,
+                })
+                .collect(),
+        }
+    }
+
+    fn summaries(deaths: &[DeathEvent]) -> Vec<String> {
+        deaths.iter().map(DeathEvent::summary).collect()
+    }
+
+    #[test]
+    fn pairs_counters_from_the_same_read() {
+        let mut tracker = DeathTracker::new();
+        let deaths = tracker.update(&delta(&["level_deaths.level_6", "enemy_deaths.spider"]));
+        assert_eq!(summaries(&deaths), ["2-2 spider"]);
+        assert_eq!(deaths[0].level, Some(6));
+        assert_eq!(deaths[0].area, Some(Area::Jungle));
+        assert_eq!(deaths[0].cause, Some("enemy_deaths.spider"));
+    }
+
+    #[test]
+    fn pairs_counters_split_across_reads() {
+        let mut tracker = DeathTracker::new();
+        let start = Instant::now();
+        assert!(tracker
+            .update_at(&delta(&["misc_deaths.spikes"]), start)
+            .is_empty());
+        let deaths = tracker.update_at(
+            &delta(&["level_deaths.level_1"]),
+            start + Duration::from_millis(100),
+        );
+        assert_eq!(summaries(&deaths), ["1-1 spikes"]);
+    }
+
+    #[test]
+    fn reports_half_a_death_after_the_timeout() {
+        let mut tracker = DeathTracker::new();
+        let start = Instant::now();
+        assert!(tracker
+            .update_at(&delta(&["level_deaths.level_13"]), start)
+            .is_empty());
+        assert!(tracker
+            .update_at(&StatsDelta::default(), start + PAIR_TIMEOUT)
+            .is_empty());
+        let deaths = tracker.update_at(
+            &StatsDelta::default(),
+            start + PAIR_TIMEOUT + Duration::from_millis(1),
+        );
+        assert_eq!(summaries(&deaths), ["4-1 unknown"]);
+        assert_eq!(deaths[0].cause, None);
+    }
+
+    #[test]
+    fn a_second_level_increment_ends_the_first_death() {
+        let mut tracker = DeathTracker::new();
+        let start = Instant::now();
+        assert!(tracker
+            .update_at(&delta(&["level_deaths.level_2"]), start)
+            .is_empty());
+        let deaths = tracker.update_at(
+            &delta(&["level_deaths.level_3"]),
+            start + Duration::from_millis(100),
+        );
+        assert_eq!(summaries(&deaths), ["1-2 unknown"]);
+
+        let deaths = tracker.update_at(
+            &delta(&["enemy_deaths.snake"]),
+            start + Duration::from_millis(200),
+        );
+        assert_eq!(summaries(&deaths), ["1-3 snake"]);
+    }
+
+    #[test]
+    fn history_keeps_the_latest_deaths() {
+        let mut tracker = DeathTracker::new();
+        let levels = [
+            "level_deaths.level_1",
+            "level_deaths.level_2",
+            "level_deaths.level_3",
+            "level_deaths.level_4",
+            "level_deaths.level_5",
+            "level_deaths.level_6",
+            "level_deaths.level_7",
+            "level_deaths.level_8",
+            "level_deaths.level_9",
+            "level_deaths.level_10",
+            "level_deaths.level_11",
+            "level_deaths.level_12",
+        ];
+        for level in levels {
+            tracker.update(&delta(&[level, "misc_deaths.spikes"]));
+        }
+
+        let history = tracker.history();
+        assert_eq!(history.len(), HISTORY_LEN);
+        assert_eq!(history[0].level, Some(3));
+        assert_eq!(history[HISTORY_LEN - 1].level, Some(12));
+    }
+}
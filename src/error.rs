@@ -0,0 +1,139 @@
+use std::fmt;
+
+use serde::Serialize;
+
+/// Why the tracker couldn't attach to the game or read its stats.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "kind")]
+pub enum TrackerError {
+    ProcessNotFound,
+    AccessDenied {
+        pid: u32,
+    },
+    ModuleNotFound {
+        pid: u32,
+    },
+    /// Nothing could be read at `addr`.
+    ReadFailed {
+        addr: usize,
+        len: usize,
+        field: Option<String>,
+    },
+    /// Fewer bytes than requested were readable at `addr`.
+    PartialRead {
+        addr: usize,
+        requested: usize,
+        read: usize,
+        field: Option<String>,
+    },
+    /// The pointer stored at `addr` can't point at game data.
+    InvalidPointer {
+        addr: usize,
+        value: u32,
+        field: Option<String>,
+    },
+    ValueOutOfRange {
+        field: String,
+        value: f64,
+    },
+    /// Consecutive samples kept disagreeing because the game was writing to them.
+    InconsistentRead {
+        attempts: usize,
+    },
+    /// An OS call failed with `code`.
+    System {
+        operation: String,
+        code: i32,
+    },
+    Other {
+        message: String,
+    },
+}
+
+impl TrackerError {
+    /// Name the field being read when a memory error happened, unless one is set already.
+    pub fn with_field(mut self, name: &str) -> Self {
+        match &mut self {
+            TrackerError::ReadFailed { field, .. }
+            | TrackerError::PartialRead { field, .. }
+            | TrackerError::InvalidPointer { field, .. } => {
+                field.get_or_insert_with(|| name.to_string());
+            }
+            _ => {}
+        }
+        self
+    }
+
+    /// The OS error from the last failed system call.
+    pub fn last_os_error(operation: &str) -> Self {
+        TrackerError::System {
+            operation: operation.to_string(),
+            code: std::io::Error::last_os_error().raw_os_error().unwrap_or(0),
+        }
+    }
+}
+
+impl From<anyhow::Error> for TrackerError {
+    fn from(err: anyhow::Error) -> Self {
+        match err.downcast::<TrackerError>() {
+            Ok(err) => err,
+            Err(err) => TrackerError::Other {
+                message: err.to_string(),
+            },
+        }
+    }
+}
+
+fn fmt_field(f: &mut fmt::Formatter<'_>, field: &Option<String>) -> fmt::Result {
+    match field {
+        Some(field) => write!(f, " ({})", field),
+        None => Ok(()),
+    }
+}
+
+impl fmt::Display for TrackerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrackerError::ProcessNotFound => write!(f, "No process found"),
+            TrackerError::AccessDenied { pid } => {
+                write!(f, "Access denied opening process {}", pid)
+            }
+            TrackerError::ModuleNotFound { pid } => {
+                write!(f, "Failed to find game module in process {}", pid)
+            }
+            TrackerError::ReadFailed { addr, len, field } => {
+                write!(f, "Failed to read {:#x} bytes at {:#x}", len, addr)?;
+                fmt_field(f, field)
+            }
+            TrackerError::PartialRead {
+                addr,
+                requested,
+                read,
+                field,
+            } => {
+                write!(
+                    f,
+                    "Read {:#x} of {:#x} bytes at {:#x}",
+                    read, requested, addr
+                )?;
+                fmt_field(f, field)
+            }
+            TrackerError::InvalidPointer { addr, value, field } => {
+                write!(f, "Invalid pointer {:#x} at {:#x}", value, addr)?;
+                fmt_field(f, field)
+            }
+            TrackerError::ValueOutOfRange { field, value } => {
+                write!(f, "Value {} out of range ({})", value, field)
+            }
+            TrackerError::InconsistentRead { attempts } => {
+                write!(f, "Stats kept changing across {} reads", attempts)
+            }
+            TrackerError::System { operation, code } => {
+                write!(f, "{} failed with OS error {}", operation, code)
+            }
+            TrackerError::Other { message } => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for TrackerError {}
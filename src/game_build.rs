@@ -0,0 +1,172 @@
+use std::fmt;
+
+use anyhow::anyhow;
+use byteorder::{ByteOrder, LittleEndian};
+use serde::{Deserialize, Serialize};
+
+use crate::mem_reader::MemorySource;
+
+// Enough of the module to cover the DOS stub and PE headers.
+const HEADER_LEN: usize = 0x400;
+
+/// Identifies a build of the game executable from its in-memory PE header.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, Hash)]
+pub struct BuildFingerprint {
+    /// `TimeDateStamp` from the COFF file header.
+    pub timestamp: u32,
+    /// `SizeOfImage` from the optional header.
+    pub image_size: u32,
+}
+
+impl BuildFingerprint {
+    pub fn read<M: MemorySource + ?Sized>(process: &M, base_addr: usize) -> anyhow::Result<Self> {
+        let header = process.read_n_bytes(base_addr, HEADER_LEN)?.into_inner();
+        Self::from_header(&header)
+    }
+
+    pub fn from_header(header: &[u8]) -> anyhow::Result<Self> {
+        let pe_offset = pe_offset(header)?;
+        Ok(Self {
+            timestamp: LittleEndian::read_u32(&header[pe_offset + 8..]),
+            image_size: LittleEndian::read_u32(&header[pe_offset + 80..]),
+        })
+    }
+}
+
+impl fmt::Display for BuildFingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "timestamp = {:#010x}, image_size = {:#x}",
+            self.timestamp, self.image_size
+        )
+    }
+}
+
+/// A section of the module as mapped in memory.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModuleSection {
+    pub name: String,
+    /// Offset of the section from the module base.
+    pub virtual_address: usize,
+    pub virtual_size: usize,
+}
+
+/// Read the section table of the module loaded at `base_addr`.
+pub fn read_sections<M: MemorySource + ?Sized>(
+    process: &M,
+    base_addr: usize,
+) -> anyhow::Result<Vec<ModuleSection>> {
+    let header = process.read_n_bytes(base_addr, HEADER_LEN)?.into_inner();
+    sections_from_header(&header)
+}
+
+pub fn sections_from_header(header: &[u8]) -> anyhow::Result<Vec<ModuleSection>> {
+    let pe_offset = pe_offset(header)?;
+    let num_sections = LittleEndian::read_u16(&header[pe_offset + 6..]) as usize;
+    let optional_header_len = LittleEndian::read_u16(&header[pe_offset + 20..]) as usize;
+    let table = pe_offset + 24 + optional_header_len;
+
+    let mut sections = Vec::with_capacity(num_sections);
+    for idx in 0..num_sections {
+        let entry = table + idx * 40;
+        if header.len() < entry + 40 {
+            return Err(anyhow!("Section table is truncated..."));
+        }
+
+        let name = &header[entry..entry + 8];
+        let name_len = name.iter().position(|b| *b == 0).unwrap_or(8);
+        sections.push(ModuleSection {
+            name: String::from_utf8_lossy(&name[..name_len]).into_owned(),
+            virtual_size: LittleEndian::read_u32(&header[entry + 8..]) as usize,
+            virtual_address: LittleEndian::read_u32(&header[entry + 12..]) as usize,
+        });
+    }
+
+    Ok(sections)
+}
+
+/// Offset of the `PE\0\0` signature, after checking the headers are complete.
+fn pe_offset(header: &[u8]) -> anyhow::Result<usize> {
+    if header.len() < 0x40 || &header[..2] != b"MZ" {
+        return Err(anyhow!("Module has no DOS header..."));
+    }
+
+    let pe_offset = LittleEndian::read_u32(&header[0x3c..]) as usize;
+    // Signature (4) + file header (20) + optional header up to SizeOfImage (60).
+    if header.len() < pe_offset + 84 || &header[pe_offset..pe_offset + 4] != b"PE\0\0" {
+        return Err(anyhow!("Module has no PE header..."));
+    }
+
+    Ok(pe_offset)
+}
+
+/// A module header with a DOS stub, a PE header and the given sections, as
+/// `(name, virtual_address, virtual_size)`.
+#[cfg(test)]
+pub(crate) fn fake_header(sections: &[(&str, u32, u32)]) -> Vec<u8> {
+    let mut header = vec![0; HEADER_LEN];
+    header[..2].copy_from_slice(b"MZ");
+    LittleEndian::write_u32(&mut header[0x3c..], 0x80);
+    header[0x80..0x84].copy_from_slice(b"PE\0\0");
+    LittleEndian::write_u16(&mut header[0x80 + 6..], sections.len() as u16);
+    LittleEndian::write_u16(&mut header[0x80 + 20..], 0xe0);
+    LittleEndian::write_u32(&mut header[0x80 + 80..], 0x20_0000);
+
+    let table = 0x80 + 24 + 0xe0;
+    for (idx, (name, virtual_address, virtual_size)) in sections.iter().enumerate() {
+        let entry = table + idx * 40;
+        header[entry..entry + name.len()].copy_from_slice(name.as_bytes());
+        LittleEndian::write_u32(&mut header[entry + 8..], *virtual_size);
+        LittleEndian::write_u32(&mut header[entry + 12..], *virtual_address);
+    }
+    header
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fingerprints_the_pe_header() {
+        let mut header = fake_header(&[]);
+        LittleEndian::write_u32(&mut header[0x80 + 8..], 0x4a3b_2c1d);
+        assert_eq!(
+            BuildFingerprint::from_header(&header).unwrap(),
+            BuildFingerprint {
+                timestamp: 0x4a3b_2c1d,
+                image_size: 0x20_0000,
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_modules_without_headers() {
+        let mut header = fake_header(&[]);
+        header[0x80] = b'X';
+        assert!(BuildFingerprint::from_header(&header).is_err());
+        assert!(BuildFingerprint::from_header(b"MZ").is_err());
+        assert!(BuildFingerprint::from_header(&[0; HEADER_LEN]).is_err());
+    }
+
+    #[test]
+    fn reads_the_section_table() {
+        let header = fake_header(&[(".text", 0x1000, 0x5000), (".data", 0x6000, 0x200)]);
+        let sections = sections_from_header(&header).unwrap();
+        assert_eq!(
+            sections,
+            [
+                ModuleSection {
+                    name: ".text".to_string(),
+                    virtual_address: 0x1000,
+                    virtual_size: 0x5000,
+                },
+                ModuleSection {
+                    name: ".data".to_string(),
+                    virtual_address: 0x6000,
+                    virtual_size: 0x200,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,344 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use anyhow::anyhow;
+use byteorder::{ReadBytesExt, LE};
+use serde::Deserialize;
+
+use crate::error::TrackerError;
+use crate::game_build::BuildFingerprint;
+use crate::mem_reader::{parse_pattern, scan_module, MemorySource};
+use crate::process::ExeMatcher;
+use crate::stats::Stats;
+
+static BUILTIN_LAYOUT: &str = include_str!("../layouts/classic.toml");
+
+/// Where a single counter lives inside the stat block.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct FieldLayout {
+    /// Dotted `Stats` key, e.g. `"enemy_deaths.spider"`.
+    pub key: String,
+    pub slot: usize,
+    /// Element of the slot's list. Element 0 is the value stored in the slot itself.
+    #[serde(default)]
+    pub index: usize,
+}
+
+/// Code that references the first root pointer, used to find it in builds where
+/// it has moved.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct Signature {
+    /// Bytes to search for, with `??` as a wildcard.
+    pub pattern: String,
+    /// Position in the match of the 32-bit absolute address of the root pointer.
+    pub operand: usize,
+}
+
+/// Declarative description of where `Stats` live in game memory.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct StatsLayout {
+    pub name: String,
+    /// Game builds this layout is known to match. Layouts without any are used as a
+    /// fallback for unrecognized builds.
+    #[serde(default)]
+    pub builds: Vec<BuildFingerprint>,
+    /// Executables to attach to. Unless another layout lists their build, processes
+    /// matched here are read with this layout.
+    #[serde(default)]
+    pub processes: Vec<ExeMatcher>,
+    pub root: Vec<usize>,
+    /// When present, replaces the first `root` offset with the one found by scanning.
+    #[serde(default)]
+    pub signature: Option<Signature>,
+    pub block_offset: usize,
+    pub slot_stride: usize,
+    pub list_pointer_offset: usize,
+    pub list_base: usize,
+    pub list_stride: usize,
+    pub fields: Vec<FieldLayout>,
+}
+
+impl StatsLayout {
+    /// The layout shipped with the tracker.
+    pub fn builtin() -> &'static StatsLayout {
+        static LAYOUT: OnceLock<StatsLayout> = OnceLock::new();
+        LAYOUT.get_or_init(|| {
+            StatsLayout::from_toml(BUILTIN_LAYOUT).expect("Built-in layout is invalid")
+        })
+    }
+
+    pub fn from_toml(source: &str) -> anyhow::Result<Self> {
+        let layout: StatsLayout = toml::from_str(source)?;
+        layout.check()?;
+        Ok(layout)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        Self::from_toml(&fs::read_to_string(path)?)
+    }
+
+    /// The layout that lists `build`, if any.
+    pub fn select<'a>(
+        layouts: &'a [StatsLayout],
+        build: &BuildFingerprint,
+    ) -> Option<&'a StatsLayout> {
+        layouts.iter().find(|layout| layout.builds.contains(build))
+    }
+
+    pub fn named<'a>(layouts: &'a [StatsLayout], name: &str) -> Option<&'a StatsLayout> {
+        layouts.iter().find(|layout| layout.name == name)
+    }
+
+    /// The first layout that doesn't claim specific builds.
+    pub fn fallback(layouts: &[StatsLayout]) -> Option<&StatsLayout> {
+        layouts.iter().find(|layout| layout.builds.is_empty())
+    }
+
+    /// The layout for `build`, or the fallback when the build isn't recognized.
+    pub fn for_build<'a>(
+        layouts: &'a [StatsLayout],
+        build: &BuildFingerprint,
+    ) -> Option<&'a StatsLayout> {
+        Self::select(layouts, build).or_else(|| Self::fallback(layouts))
+    }
+
+    /// Resolve `signature` against the module at `base_addr`, returning a layout with
+    /// a fixed root so the scan only has to happen once per attach.
+    pub fn resolved<M: MemorySource + ?Sized>(
+        &self,
+        process: &M,
+        base_addr: usize,
+    ) -> anyhow::Result<StatsLayout> {
+        let signature = match &self.signature {
+            Some(signature) => signature,
+            None => return Ok(self.clone()),
+        };
+
+        let pattern = parse_pattern(&signature.pattern)?;
+        let found = scan_module(process, base_addr, &pattern)?
+            .ok_or_else(|| anyhow!("Signature for layout {:?} not found", self.name))?;
+        let root_addr = process.read_uptr32_t(found + signature.operand)? as usize;
+        if root_addr < base_addr {
+            return Err(anyhow!("Signature points outside the module"));
+        }
+
+        let mut layout = self.clone();
+        layout.root[0] = root_addr - base_addr;
+        layout.signature = None;
+        Ok(layout)
+    }
+
+    fn check(&self) -> anyhow::Result<()> {
+        if let Some(signature) = &self.signature {
+            let pattern = parse_pattern(&signature.pattern)?;
+            if self.root.is_empty() {
+                return Err(anyhow!("Layout with a signature needs a root"));
+            }
+            if signature.operand + 4 > pattern.len() {
+                return Err(anyhow!("Signature operand is past the end of the pattern"));
+            }
+        }
+
+        let mut stats = Stats::default();
+        for field in &self.fields {
+            if stats.field_mut(&field.key).is_none() {
+                return Err(anyhow!("Unknown stat in layout: {}", field.key));
+            }
+        }
+        Ok(())
+    }
+
+    /// Follow `root` from the module base to the start of the stat block.
+    pub fn block_addr<M: MemorySource + ?Sized>(
+        &self,
+        process: &M,
+        base_addr: usize,
+    ) -> Result<usize, TrackerError> {
+        let mut addr = base_addr;
+        for offset in &self.root {
+            addr = read_pointer(process, addr + offset, "root")?;
+        }
+        Ok(addr + self.block_offset)
+    }
+
+    pub fn read_stats<M: MemorySource + ?Sized>(
+        &self,
+        process: &M,
+        base_addr: usize,
+        stats: &mut Stats,
+    ) -> Result<(), TrackerError> {
+        let block_addr = self.block_addr(process, base_addr)?;
+
+        let last_slot = self.fields.iter().map(|f| f.slot).max().unwrap_or(0);
+        let mut block = read_exact(
+            process,
+            block_addr,
+            last_slot * self.slot_stride + 8,
+            "stat block",
+        )?;
+
+        // Highest list element needed per slot, so each list is read once.
+        let mut list_lens: BTreeMap<usize, usize> = BTreeMap::new();
+        for field in self.fields.iter().filter(|f| f.index > 0) {
+            let len = list_lens.entry(field.slot).or_default();
+            *len = (*len).max(field.index);
+        }
+
+        let mut lists: BTreeMap<usize, Cursor<Vec<u8>>> = BTreeMap::new();
+        for (&slot, &len) in &list_lens {
+            let name = self.list_name(slot);
+            let list_addr = read_pointer(
+                process,
+                block_addr + slot * self.slot_stride + self.list_pointer_offset,
+                &name,
+            )?;
+            let list_addr = read_pointer(process, list_addr, &name)?;
+            let list = read_exact(
+                process,
+                list_addr + self.list_base,
+                (len - 1) * self.list_stride + 8,
+                &name,
+            )?;
+            lists.insert(slot, list);
+        }
+
+        for field in &self.fields {
+            let value = if field.index == 0 {
+                block.set_position((field.slot * self.slot_stride) as u64);
+                block.read_f64::<LE>()
+            } else {
+                let list = lists
+                    .get_mut(&field.slot)
+                    .expect("List was read for every indexed slot");
+                list.set_position(((field.index - 1) * self.list_stride) as u64);
+                list.read_f64::<LE>()
+            }
+            .expect("Buffers are read to cover every field");
+
+            if let Some(stat) = stats.field_mut(&field.key) {
+                *stat = value;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Describes the list in `slot` for errors, e.g. `"enemy_deaths list"`.
+    fn list_name(&self, slot: usize) -> String {
+        let group = self
+            .fields
+            .iter()
+            .find(|field| field.slot == slot)
+            .and_then(|field| field.key.split('.').next())
+            .unwrap_or("unknown");
+        format!("{} list", group)
+    }
+}
+
+/// Lowest address a pointer to game data can have.
+const MIN_POINTER: u32 = 0x10000;
+
+fn read_pointer<M: MemorySource + ?Sized>(
+    process: &M,
+    addr: usize,
+    field: &str,
+) -> Result<usize, TrackerError> {
+    let value = process
+        .read_uptr32_t(addr)
+        .map_err(|err| err.with_field(field))?;
+    if value < MIN_POINTER {
+        return Err(TrackerError::InvalidPointer {
+            addr,
+            value,
+            field: Some(field.to_string()),
+        });
+    }
+    Ok(value as usize)
+}
+
+fn read_exact<M: MemorySource + ?Sized>(
+    process: &M,
+    addr: usize,
+    len: usize,
+    field: &str,
+) -> Result<Cursor<Vec<u8>>, TrackerError> {
+    let cursor = process
+        .read_n_bytes(addr, len)
+        .map_err(|err| err.with_field(field))?;
+    if cursor.get_ref().len() < len {
+        return Err(TrackerError::PartialRead {
+            addr,
+            requested: len,
+            read: cursor.get_ref().len(),
+            field: Some(field.to_string()),
+        });
+    }
+    Ok(cursor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BUILD: BuildFingerprint = BuildFingerprint {
+        timestamp: 0x4a3b_2c1d,
+        image_size: 0x20_0000,
+    };
+
+    fn layout(name: &str, builds: &[BuildFingerprint]) -> StatsLayout {
+        StatsLayout {
+            name: name.to_string(),
+            builds: builds.to_vec(),
+            ..StatsLayout::builtin().clone()
+        }
+    }
+
+    #[test]
+    fn selects_the_layout_listing_the_build() {
+        let other = BuildFingerprint {
+            timestamp: 1,
+            ..BUILD
+        };
+        let layouts = [
+            layout("fallback", &[]),
+            layout("other", &[other]),
+            layout("listed", &[BUILD]),
+        ];
+        assert_eq!(
+            StatsLayout::select(&layouts, &BUILD).map(|layout| layout.name.as_str()),
+            Some("listed")
+        );
+        assert_eq!(
+            StatsLayout::for_build(&layouts, &BUILD).map(|layout| layout.name.as_str()),
+            Some("listed")
+        );
+    }
+
+    #[test]
+    fn unlisted_builds_use_the_fallback() {
+        let layouts = [layout("listed", &[BUILD]), layout("fallback", &[])];
+        let unknown = BuildFingerprint {
+            image_size: 0x10_0000,
+            ..BUILD
+        };
+        assert!(StatsLayout::select(&layouts, &unknown).is_none());
+        assert_eq!(
+            StatsLayout::for_build(&layouts, &unknown).map(|layout| layout.name.as_str()),
+            Some("fallback")
+        );
+        assert!(StatsLayout::for_build(&layouts[..1], &unknown).is_none());
+    }
+
+    #[test]
+    fn parses_builds_from_toml() {
+        let source = BUILTIN_LAYOUT.replace(
+            "builds = []",
+            "builds = [{ timestamp = 0x4a3b2c1d, image_size = 0x200000 }]",
+        );
+        let layout = StatsLayout::from_toml(&source).unwrap();
+        assert_eq!(layout.builds, [BUILD]);
+    }
+}
@@ -1,2 +1,10 @@
+pub mod deaths;
+pub mod error;
+pub mod game_build;
+pub mod layout;
 pub mod mem_reader;
+pub mod process;
+pub mod ruleset;
+pub mod schema;
+pub mod snapshot;
 pub mod stats;
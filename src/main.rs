@@ -1,6 +1,7 @@
 use std::collections::HashMap;
 use std::future::Future;
 use std::pin::Pin;
+use std::sync::mpsc;
 use std::sync::Arc;
 use std::task::{Context, Poll};
 
@@ -13,7 +14,15 @@ use static_files::Resource;
 use tokio::select;
 use tokio::sync::broadcast::{channel, Receiver, Sender};
 
-use classic_stats_tracker::mem_reader::{run_forever, TrackerMessage};
+use classic_stats_tracker::game_build::BuildFingerprint;
+use classic_stats_tracker::layout::StatsLayout;
+use classic_stats_tracker::mem_reader::{
+    capture_snapshot, run_forever, TrackerCommand, TrackerConfig, TrackerMessage,
+};
+use classic_stats_tracker::process::{ExeMatcher, ExePattern};
+use classic_stats_tracker::ruleset::Ruleset;
+use classic_stats_tracker::snapshot::Snapshot;
+use classic_stats_tracker::stats::{StatKey, Stats};
 
 include!(concat!(env!("OUT_DIR"), "/generated.rs"));
 
@@ -27,6 +36,7 @@ async fn shutdown_signal() {
 struct Tracker {
     static_assets: Arc<HashMap<&'static str, Resource>>,
     stats_tx: Sender<TrackerMessage>,
+    command_tx: mpsc::Sender<TrackerCommand>,
 }
 
 impl Service<Request<Body>> for Tracker {
@@ -54,6 +64,17 @@ impl Service<Request<Body>> for Tracker {
             return Box::pin(async { response });
         }
 
+        // Describe every counter, so the frontend doesn't have to
+        if path == "/stats/catalog" {
+            let response = Response::builder()
+                .header(CONTENT_TYPE, "application/json")
+                .status(StatusCode::OK)
+                .body(Body::from(
+                    serde_json::to_string(StatKey::ALL).unwrap_or_default(),
+                ));
+            return Box::pin(async { response });
+        }
+
         // Handle Websockets
         if path == "/ws/" && hyper_tungstenite::is_upgrade_request(&req) {
             let (response, websocket) = match hyper_tungstenite::upgrade(&mut req, None) {
@@ -68,9 +89,10 @@ impl Service<Request<Body>> for Tracker {
             };
 
             let stats_rx = self.stats_tx.subscribe();
+            let command_tx = self.command_tx.clone();
             // Spawn a task to handle the websocket connection.
             tokio::spawn(async move {
-                if let Err(e) = serve_websocket(websocket, stats_rx).await {
+                if let Err(e) = serve_websocket(websocket, stats_rx, command_tx).await {
                     eprintln!("Error in websocket connection: {}", e);
                 }
             });
@@ -88,6 +110,7 @@ impl Service<Request<Body>> for Tracker {
 async fn serve_websocket(
     websocket: HyperWebsocket,
     mut stats_rx: Receiver<TrackerMessage>,
+    command_tx: mpsc::Sender<TrackerCommand>,
 ) -> Result<(), anyhow::Error> {
     let mut websocket = websocket.await?;
 
@@ -97,9 +120,11 @@ async fn serve_websocket(
                 match val {
                     Some(msg) => {
                         match msg? {
-                            // Not currently doing anything with
                             tungstenite::Message::Text(msg) => {
-                                websocket.send(tungstenite::Message::text(msg)).await?;
+                                match serde_json::from_str::<TrackerCommand>(&msg) {
+                                    Ok(command) => command_tx.send(command)?,
+                                    Err(err) => eprintln!("Failed to understand command: {}", err),
+                                }
                             }
                             tungstenite::Message::Binary(_msg) => {}
                             tungstenite::Message::Ping(_msg) => {}
@@ -139,6 +164,7 @@ async fn serve_websocket(
 struct MakeSvc {
     static_assets: Arc<HashMap<&'static str, Resource>>,
     stats_tx: Sender<TrackerMessage>,
+    command_tx: mpsc::Sender<TrackerCommand>,
 }
 
 impl<T> Service<T> for MakeSvc {
@@ -154,25 +180,123 @@ impl<T> Service<T> for MakeSvc {
         let assets = self.static_assets.clone();
 
         let stats_tx = self.stats_tx.clone();
+        let command_tx = self.command_tx.clone();
         let fut = async move {
             Ok(Tracker {
                 static_assets: assets,
                 stats_tx,
+                command_tx,
             })
         };
         Box::pin(fut)
     }
 }
 
+/// Record the game's stats memory to `path` for offline parsing.
+fn dump(path: &str, config: &TrackerConfig) -> anyhow::Result<()> {
+    let snapshot = capture_snapshot(config)?;
+    snapshot.save(path)?;
+    println!("Wrote {} regions to {}", snapshot.regions.len(), path);
+    Ok(())
+}
+
+/// Parse a snapshot written by `dump` and print the stats it contains.
+fn replay(path: &str, layouts: &[StatsLayout]) -> anyhow::Result<()> {
+    let snapshot = Snapshot::load(path)?;
+    let layout = match BuildFingerprint::read(&snapshot, snapshot.base_addr) {
+        Ok(build) => {
+            println!("Game build: {}", build);
+            StatsLayout::for_build(layouts, &build)
+        }
+        Err(_) => StatsLayout::fallback(layouts),
+    }
+    .ok_or_else(|| anyhow::anyhow!("No layout for this snapshot"))?;
+    let layout = layout.resolved(&snapshot, snapshot.base_addr)?;
+    let stats = Stats::read(&snapshot, snapshot.base_addr, &layout)?;
+    if let Err(err) = stats.validate() {
+        println!("Snapshot failed validation: {}", err);
+    }
+    stats.pprint();
+
+    for ruleset in Ruleset::ALL {
+        println!();
+        ruleset.completion(&stats).pprint();
+    }
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let (tx, _) = channel::<TrackerMessage>(1);
+    let mut args: Vec<String> = std::env::args().collect();
+
+    // User-supplied layouts take priority over the built-in one, e.g. to fix an
+    // offset or to support another build.
+    let mut layouts = Vec::new();
+    while let Some(idx) = args.iter().position(|arg| arg == "--layout") {
+        if idx + 1 >= args.len() {
+            anyhow::bail!("Usage: {} --layout <layout file>", args[0]);
+        }
+        let path = args.remove(idx + 1);
+        args.remove(idx);
+        layouts.push(StatsLayout::load(&path)?);
+    }
+    layouts.push(StatsLayout::builtin().clone());
+
+    let mut config = TrackerConfig {
+        layouts,
+        ..TrackerConfig::default()
+    };
+
+    // Accept every read as-is instead of waiting for two matching samples.
+    if let Some(idx) = args.iter().position(|arg| arg == "--single-read") {
+        args.remove(idx);
+        config.consistency_retries = None;
+    }
+
+    // Also attach to executables matching these patterns, e.g. renamed or modded builds.
+    while let Some(idx) = args.iter().position(|arg| arg == "--exe") {
+        if idx + 1 >= args.len() {
+            anyhow::bail!("Usage: {} --exe <file name pattern>", args[0]);
+        }
+        let pattern = args.remove(idx + 1);
+        args.remove(idx);
+        config.exe_matchers.push(ExeMatcher {
+            pattern: ExePattern::Glob(pattern),
+            ignore_case: true,
+        });
+    }
+
+    // Pick a process when more than one copy of the game is running.
+    if let Some(idx) = args.iter().position(|arg| arg == "--select") {
+        if idx + 1 >= args.len() {
+            anyhow::bail!(
+                "Usage: {} --select first|newest|prompt|pid:<pid>|path:<path>",
+                args[0]
+            );
+        }
+        config.selector = args.remove(idx + 1).parse()?;
+        args.remove(idx);
+    }
+
+    match (args.get(1).map(String::as_str), args.get(2)) {
+        (Some("dump"), Some(path)) => return dump(path, &config),
+        (Some("replay"), Some(path)) => return replay(path, &config.layouts),
+        (Some("dump" | "replay"), None) => {
+            anyhow::bail!("Usage: {} dump|replay <snapshot file>", args[0]);
+        }
+        _ => {}
+    }
+
+    // Room for the burst of messages sent after one read, so slow clients don't skip any.
+    let (tx, _) = channel::<TrackerMessage>(64);
     let stats_tx = tx.clone();
-    std::thread::spawn(|| run_forever(stats_tx));
+    let (command_tx, command_rx) = mpsc::channel::<TrackerCommand>();
+    std::thread::spawn(|| run_forever(stats_tx, command_rx, config));
     let addr = ([127, 0, 0, 1], 4224).into();
     let service = MakeSvc {
         static_assets: Arc::new(generate()),
         stats_tx: tx,
+        command_tx,
     };
 
     let server = Server::bind(&addr).serve(service);
@@ -1,268 +1,1010 @@
 use std::io::Cursor;
-use std::mem::size_of;
+use std::sync::mpsc::Receiver;
 use std::thread::sleep;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use anyhow::anyhow;
 use byteorder::{ByteOrder, LittleEndian};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use tokio::sync::broadcast::Sender;
-use winapi::shared::minwindef::{DWORD, HMODULE, LPCVOID, LPVOID, MAX_PATH};
-use winapi::shared::ntdef::HANDLE;
-use winapi::um::handleapi::{CloseHandle, INVALID_HANDLE_VALUE};
-use winapi::um::memoryapi::ReadProcessMemory;
-use winapi::um::minwinbase::STILL_ACTIVE;
-use winapi::um::processthreadsapi::{GetExitCodeProcess, OpenProcess};
-use winapi::um::psapi::{EnumProcessModules, GetModuleFileNameExA};
-use winapi::um::tlhelp32::{
-    CreateToolhelp32Snapshot, Process32First, Process32Next, PROCESSENTRY32, TH32CS_SNAPPROCESS,
+
+use crate::deaths::{DeathEvent, DeathTracker};
+use crate::error::TrackerError;
+use crate::game_build::{read_sections, BuildFingerprint};
+use crate::layout::StatsLayout;
+use crate::process::{ExeMatcher, ProcessCandidate, ProcessRule, ProcessSelector};
+use crate::ruleset::{Completion, Ruleset};
+use crate::snapshot::Snapshot;
+use crate::stats::{Stats, StatsDelta};
+
+#[cfg(target_os = "linux")]
+mod linux;
+#[cfg(windows)]
+mod windows;
+
+#[cfg(target_os = "linux")]
+use linux::{
+    close_process, get_base_addr, is_running, list_game_processes, open_process, process_id,
+    Process,
+};
+#[cfg(windows)]
+use windows::{
+    close_process, get_base_addr, is_running, list_game_processes, open_process, process_id,
+    Process,
 };
-use winapi::um::winnt::{PROCESS_QUERY_INFORMATION, PROCESS_VM_READ};
-
-use crate::stats::Stats;
-
-static EXE_NAME: &'static [i8; 13] = &[
-    'S' as i8, 'p' as i8, 'e' as i8, 'l' as i8, 'u' as i8, 'n' as i8, 'k' as i8, 'y' as i8,
-    '.' as i8, 'e' as i8, 'x' as i8, 'e' as i8, '\0' as i8,
-];
-
-fn get_spelunky_process() -> Result<HANDLE, anyhow::Error> {
-    unsafe {
-        let process_snap = CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0);
-
-        let mut process: PROCESSENTRY32 = PROCESSENTRY32 {
-            dwSize: size_of::<PROCESSENTRY32>()
-                .try_into()
-                .expect("Failed to get size of PROCESSENTRY32"),
-            cntUsage: 0,
-            th32ProcessID: 0,
-            th32DefaultHeapID: 0,
-            th32ModuleID: 0,
-            cntThreads: 0,
-            th32ParentProcessID: 0,
-            pcPriClassBase: 0,
-            dwFlags: 0,
-            szExeFile: [0; MAX_PATH],
-        };
 
-        if process_snap == INVALID_HANDLE_VALUE {
-            return Err(anyhow!("Failed to get process list handle..."));
+/// A readable view of the game's address space.
+///
+/// `Stats` is parsed through this trait so the same code can run against a
+/// live process, a recorded snapshot or an in-memory fake.
+pub trait MemorySource {
+    /// Read a little-endian 32-bit pointer at `addr`.
+    fn read_uptr32_t(&self, addr: usize) -> Result<u32, TrackerError> {
+        let buf = self.read_n_bytes(addr, 4)?.into_inner();
+        if buf.len() < 4 {
+            return Err(TrackerError::PartialRead {
+                addr,
+                requested: 4,
+                read: buf.len(),
+                field: None,
+            });
         }
+        Ok(LittleEndian::read_u32(&buf))
+    }
+
+    /// Read up to `num_bytes` bytes starting at `addr`.
+    fn read_n_bytes(&self, addr: usize, num_bytes: usize) -> Result<Cursor<Vec<u8>>, TrackerError>;
+}
+
+/// Sparse address space for tests, readable wherever bytes were written.
+#[cfg(test)]
+#[derive(Clone, Debug, Default)]
+pub(crate) struct FakeMemory {
+    bytes: std::collections::BTreeMap<usize, u8>,
+}
 
-        if Process32First(process_snap, &mut process) == 0 {
-            return Err(anyhow!("Failed to get first process..."));
+#[cfg(test)]
+impl FakeMemory {
+    pub fn write(&mut self, addr: usize, bytes: &[u8]) {
+        for (offset, byte) in bytes.iter().enumerate() {
+            self.bytes.insert(addr + offset, *byte);
         }
+    }
 
-        loop {
-            if &process.szExeFile[..EXE_NAME.len()] == EXE_NAME {
-                CloseHandle(process_snap);
+    pub fn write_u32(&mut self, addr: usize, value: u32) {
+        self.write(addr, &value.to_le_bytes());
+    }
 
-                let process_handle = OpenProcess(
-                    PROCESS_QUERY_INFORMATION | PROCESS_VM_READ,
-                    0,
-                    process.th32ProcessID,
-                );
+    pub fn write_f64(&mut self, addr: usize, value: f64) {
+        self.write(addr, &value.to_le_bytes());
+    }
+}
 
-                if process_handle == winapi::shared::ntdef::NULL {
-                    return Err(anyhow!("Failed to open process..."));
-                }
+#[cfg(test)]
+impl MemorySource for FakeMemory {
+    fn read_n_bytes(&self, addr: usize, num_bytes: usize) -> Result<Cursor<Vec<u8>>, TrackerError> {
+        let bytes: Vec<u8> = (addr..addr + num_bytes)
+            .map_while(|addr| self.bytes.get(&addr).copied())
+            .collect();
+        if bytes.is_empty() {
+            return Err(TrackerError::ReadFailed {
+                addr,
+                len: num_bytes,
+                field: None,
+            });
+        }
+        Ok(Cursor::new(bytes))
+    }
+}
 
-                return Ok(process_handle);
-            }
+/// Largest single read while scanning a module.
+const SCAN_CHUNK_LEN: usize = 0x10000;
 
-            if Process32Next(process_snap, &mut process) == 0 {
-                return Err(anyhow!("No process found!"));
+/// Parse a byte pattern like `"8B 0D ?? ?? ?? ?? 8B 41 04"`, where `??` matches any byte.
+pub fn parse_pattern(pattern: &str) -> anyhow::Result<Vec<Option<u8>>> {
+    let bytes = pattern
+        .split_whitespace()
+        .map(|byte| match byte {
+            "?" | "??" => Ok(None),
+            _ => u8::from_str_radix(byte, 16)
+                .map(Some)
+                .map_err(|_| anyhow!("Invalid byte {:?} in pattern", byte)),
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    if bytes.is_empty() {
+        return Err(anyhow!("Pattern is empty"));
+    }
+    Ok(bytes)
+}
+
+/// Offset of the first match of `pattern` in `haystack`.
+pub fn find_pattern(haystack: &[u8], pattern: &[Option<u8>]) -> Option<usize> {
+    if pattern.is_empty() {
+        return None;
+    }
+
+    haystack.windows(pattern.len()).position(|window| {
+        window
+            .iter()
+            .zip(pattern)
+            .all(|(byte, expected)| expected.is_none_or(|expected| *byte == expected))
+    })
+}
+
+/// Scan every section of the module at `base_addr` for `pattern` and return the
+/// address of the first match.
+pub fn scan_module<M: MemorySource + ?Sized>(
+    process: &M,
+    base_addr: usize,
+    pattern: &[Option<u8>],
+) -> anyhow::Result<Option<usize>> {
+    for section in read_sections(process, base_addr)? {
+        let start = base_addr + section.virtual_address;
+        let end = start + section.virtual_size;
+
+        let mut addr = start;
+        while addr < end {
+            let len = SCAN_CHUNK_LEN.min(end - addr);
+            // Overlap chunks so matches straddling a boundary aren't missed.
+            let read_len = (len + pattern.len() - 1).min(end - addr);
+            if let Ok(chunk) = process.read_n_bytes(addr, read_len) {
+                if let Some(offset) = find_pattern(chunk.get_ref(), pattern) {
+                    return Ok(Some(addr + offset));
+                }
             }
+            addr += len;
         }
     }
+
+    Ok(None)
 }
 
-fn get_base_addr(process: HANDLE) -> anyhow::Result<usize> {
-    // Get Module name of EXE
-    let mut process_image_filename = [0; MAX_PATH];
-    unsafe {
-        let result = GetModuleFileNameExA(
-            process,
-            0 as HMODULE,
-            process_image_filename.as_mut_ptr(),
-            MAX_PATH as u32,
+#[cfg(test)]
+mod scan_tests {
+    use super::*;
+    use crate::game_build::fake_header;
+    use crate::snapshot::{Region, Snapshot};
+
+    #[test]
+    fn parses_patterns() {
+        assert_eq!(
+            parse_pattern("8B 0d ?? ? 04").unwrap(),
+            [Some(0x8b), Some(0x0d), None, None, Some(0x04)]
         );
-        if result == 0 {
-            return Err(anyhow!("Failed to get process module name..."));
-        }
+        assert!(parse_pattern("").is_err());
+        assert!(parse_pattern("8B GG").is_err());
+        assert!(parse_pattern("8B 100").is_err());
     }
 
-    // Get handles for all modules in process.
-    let mut module_handles: [HMODULE; 1024] = [0 as HMODULE; 1024];
-    let hmodule_size: usize = size_of::<HMODULE>()
-        .try_into()
-        .expect("Failed to get size of HMODULE");
-    let mut bytes_written = 0;
-
-    unsafe {
-        let result = EnumProcessModules(
-            process,
-            module_handles.as_mut_ptr(),
-            size_of::<[HMODULE; 1024]>()
-                .try_into()
-                .expect("Failed to get size for modules"),
-            &mut bytes_written,
+    #[test]
+    fn finds_patterns_with_wildcards() {
+        let pattern = parse_pattern("8B ?? 04").unwrap();
+        assert_eq!(
+            find_pattern(&[0, 0x8b, 0x0d, 0x04, 0x8b], &pattern),
+            Some(1)
         );
-        if result == 0 {
-            return Err(anyhow!("Failed to enumerate process modules..."));
-        }
+        assert_eq!(find_pattern(&[0x8b, 0x0d, 0x05], &pattern), None);
+        assert_eq!(find_pattern(&[0x8b, 0x0d], &pattern), None);
+        assert_eq!(find_pattern(&[0x8b], &[]), None);
     }
 
-    let num_modules = bytes_written as usize / hmodule_size;
+    #[test]
+    fn scans_module_across_chunks() {
+        let base_addr = 0x400000;
+        let section_len = SCAN_CHUNK_LEN * 2;
+        let mut code = vec![0; section_len];
+        // Straddle the boundary between the first and second chunk.
+        let at = SCAN_CHUNK_LEN - 3;
+        code[at..at + 6].copy_from_slice(&[0x8b, 0x0d, 0x24, 0xf1, 0x58, 0x00]);
+        let snapshot = Snapshot {
+            base_addr,
+            regions: vec![
+                Region {
+                    addr: base_addr,
+                    bytes: fake_header(&[(".text", 0x1000, section_len as u32)]),
+                },
+                Region {
+                    addr: base_addr + 0x1000,
+                    bytes: code,
+                },
+            ],
+        };
+
+        let pattern = parse_pattern("8B 0D ?? ?? ?? 00").unwrap();
+        assert_eq!(
+            scan_module(&snapshot, base_addr, &pattern).unwrap(),
+            Some(base_addr + 0x1000 + at)
+        );
+        let missing = parse_pattern("8B 0D 00 00").unwrap();
+        assert_eq!(scan_module(&snapshot, base_addr, &missing).unwrap(), None);
+    }
 
-    // Enumerate Modules to find handle for EXE module
-    for idx in 0..num_modules {
-        let mut module_filename = [0; MAX_PATH];
-        unsafe {
-            let result = GetModuleFileNameExA(
+    #[test]
+    fn signature_replaces_the_root_offset() {
+        let base_addr = 0x400000;
+        let mut code = vec![0; 0x100];
+        code[0x40..0x42].copy_from_slice(&[0x8b, 0x0d]);
+        code[0x42..0x46].copy_from_slice(&((base_addr + 0x0019_0000) as u32).to_le_bytes());
+        let snapshot = Snapshot {
+            base_addr,
+            regions: vec![
+                Region {
+                    addr: base_addr,
+                    bytes: fake_header(&[(".text", 0x1000, 0x100)]),
+                },
+                Region {
+                    addr: base_addr + 0x1000,
+                    bytes: code,
+                },
+            ],
+        };
+
+        let mut layout = StatsLayout::builtin().clone();
+        layout.signature = Some(crate::layout::Signature {
+            pattern: "8B 0D ?? ?? ?? ??".to_string(),
+            operand: 2,
+        });
+        let resolved = layout.resolved(&snapshot, base_addr).unwrap();
+        assert_eq!(resolved.root, [0x0019_0000, 0x4]);
+        assert_eq!(resolved.signature, None);
+    }
+}
+
+/// An open game process, closed when dropped.
+pub struct GameProcess {
+    process: Process,
+    pid: u32,
+    base_addr: usize,
+    build: BuildFingerprint,
+}
+
+impl GameProcess {
+    /// Open `candidate` and find the game module and build in it.
+    pub fn attach(candidate: &ProcessCandidate) -> Result<Self, TrackerError> {
+        let process = open_process(candidate)?;
+        let found = get_base_addr(&process).and_then(|base_addr| {
+            let build = BuildFingerprint::read(&process, base_addr)?;
+            Ok((base_addr, build))
+        });
+        match found {
+            Ok((base_addr, build)) => Ok(GameProcess {
+                pid: process_id(&process),
                 process,
-                module_handles[idx],
-                module_filename.as_mut_ptr(),
-                MAX_PATH as u32,
-            );
-            if result == 0 {
-                continue;
+                base_addr,
+                build,
+            }),
+            Err(err) => {
+                close_process(&process);
+                Err(err)
             }
         }
+    }
 
-        if module_filename != process_image_filename {
-            continue;
-        }
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    /// Address the game executable is loaded at.
+    pub fn base_addr(&self) -> usize {
+        self.base_addr
+    }
 
-        // Found the exe module base address
-        return Ok(module_handles[idx] as usize);
+    pub fn build(&self) -> BuildFingerprint {
+        self.build
     }
 
-    Err(anyhow!("Failed to find module..."))
+    pub fn has_exited(&self) -> bool {
+        !is_running(&self.process)
+    }
 }
 
-pub(crate) fn read_uptr32_t(process: HANDLE, addr: usize) -> anyhow::Result<u32> {
-    let mut buf: [u8; 4] = [0; 4];
-    let mut bytes_read = 0;
-    unsafe {
-        if ReadProcessMemory(
-            process,
-            addr as LPCVOID,
-            buf.as_mut_ptr() as LPVOID,
-            4,
-            &mut bytes_read,
-        ) == 0
-        {
-            return Err(anyhow!("Failed to read memory"));
-        }
-    };
-    Ok(LittleEndian::read_u32(&buf))
-}
-
-pub(crate) fn read_n_bytes(
-    process: HANDLE,
-    addr: usize,
-    num_bytes: usize,
-) -> anyhow::Result<Cursor<Vec<u8>>> {
-    let mut buf: Vec<u8> = Vec::with_capacity(num_bytes);
-    let mut bytes_read = 0;
-    unsafe {
-        if ReadProcessMemory(
-            process,
-            addr as LPCVOID,
-            buf.as_mut_ptr() as LPVOID,
-            num_bytes,
-            &mut bytes_read,
-        ) == 0
-        {
-            return Err(anyhow!("Failed to read memory"));
+impl Drop for GameProcess {
+    fn drop(&mut self) {
+        close_process(&self.process);
+    }
+}
+
+impl MemorySource for GameProcess {
+    fn read_uptr32_t(&self, addr: usize) -> Result<u32, TrackerError> {
+        self.process.read_uptr32_t(addr)
+    }
+
+    fn read_n_bytes(&self, addr: usize, num_bytes: usize) -> Result<Cursor<Vec<u8>>, TrackerError> {
+        self.process.read_n_bytes(addr, num_bytes)
+    }
+}
+
+/// Attach to the running game that `selector` picks out of `candidates`.
+fn get_spelunky_process(
+    candidates: &[ProcessCandidate],
+    selector: &ProcessSelector,
+) -> Result<(GameProcess, ProcessCandidate), TrackerError> {
+    match selector.choose(candidates) {
+        Some(candidate) => Ok((GameProcess::attach(candidate)?, candidate.clone())),
+        None if candidates.is_empty() => Err(TrackerError::ProcessNotFound),
+        None => Err(TrackerError::Other {
+            message: format!(
+                "{} game processes running, none match {:?}",
+                candidates.len(),
+                selector
+            ),
+        }),
+    }
+}
+
+/// Attach to a running game once and record the memory behind its stats.
+pub fn capture_snapshot(config: &TrackerConfig) -> anyhow::Result<Snapshot> {
+    let candidates = list_game_processes(&config.process_rules())?;
+    let (process, candidate) = get_spelunky_process(&candidates, &config.selector)?;
+    let build = process.build();
+    let layout = StatsLayout::select(&config.layouts, &build)
+        .or_else(|| fallback_layout(&config.layouts, &candidate))
+        .ok_or_else(|| anyhow!("No layout for game build ({})", build))?;
+    Snapshot::capture(&process, process.base_addr(), layout)
+}
+
+/// Why the tracker moved into its current attach state.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Transition {
+    /// When the state was entered, in milliseconds since the Unix epoch.
+    pub timestamp: u64,
+    pub reason: String,
+    pub error: Option<TrackerError>,
+}
+
+/// The current time in milliseconds since the Unix epoch.
+pub(crate) fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+impl Transition {
+    fn now(reason: String, error: Option<TrackerError>) -> Self {
+        Self {
+            timestamp: unix_millis(),
+            reason,
+            error,
         }
-        buf.set_len(bytes_read);
-    };
+    }
+}
 
-    Ok(Cursor::new(buf))
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum AttachState {
+    Searching,
+    ProcessFound,
+    WaitingForGameData,
+    Attached,
+    ProcessExited,
 }
 
 #[derive(Clone, Serialize)]
 #[serde(tag = "type")]
+#[allow(clippy::large_enum_variant)]
 pub enum TrackerMessage {
-    Connecting,
-    Payload { stats: Stats },
+    /// Looking for the game process.
+    Searching(Transition),
+    /// Opened the game process and picked a layout.
+    ProcessFound(Transition),
+    /// The game is running but its stats can't be read yet, e.g. at the title screen.
+    WaitingForGameData(Transition),
+    /// Stats are being read; `Payload` messages follow.
+    Attached(Transition),
+    /// The attached game process went away.
+    ProcessExited(Transition),
+    Payload {
+        stats: Stats,
+    },
+    /// The attached executable isn't listed by any layout. `fallback` names the
+    /// layout used instead, if there is one.
+    UnknownBuild {
+        build: BuildFingerprint,
+        fallback: Option<String>,
+    },
+    /// The counters that changed since the last read.
+    Changes {
+        delta: StatsDelta,
+    },
+    /// Progress through a ruleset, sent for every ruleset along with stats.
+    Completion {
+        completion: Completion,
+    },
+    /// Everything a ruleset asks for has just been collected.
+    RunComplete {
+        ruleset: Ruleset,
+    },
+    /// The player just died.
+    Death {
+        death: DeathEvent,
+    },
+    /// The most recent deaths, oldest first.
+    DeathHistory {
+        deaths: Vec<DeathEvent>,
+    },
+    /// Reading stats from the attached game failed.
+    Error {
+        error: TrackerError,
+    },
+    /// Running copies of the game, sent when they change or on request.
+    ProcessCandidates {
+        candidates: Vec<ProcessCandidate>,
+        selector: ProcessSelector,
+    },
 }
 
-pub fn run_forever(tx: Sender<TrackerMessage>) -> anyhow::Result<()> {
-    let mut connecting = false;
-    loop {
-        if !connecting {
-            println!("Looking for Spelunky.exe (Classic)...");
-            connecting = true;
+/// Requests from clients to `run_forever`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type")]
+pub enum TrackerCommand {
+    /// Switch to the process picked by `selector`, detaching from the current one.
+    SelectProcess { selector: ProcessSelector },
+    /// Send the current `ProcessCandidates`.
+    ListProcesses,
+}
+
+/// Settings for `run_forever`.
+#[derive(Clone, Debug)]
+pub struct TrackerConfig {
+    /// Candidate layouts, in priority order.
+    pub layouts: Vec<StatsLayout>,
+    /// When set, only accept stats once two consecutive reads agree, re-reading at
+    /// most this many extra times. `None` accepts every single read.
+    pub consistency_retries: Option<usize>,
+    /// Which process to attach to when several are running.
+    pub selector: ProcessSelector,
+    /// Executables to attach to besides those the layouts list.
+    pub exe_matchers: Vec<ExeMatcher>,
+}
+
+impl TrackerConfig {
+    /// Rules for finding the game: each layout's own, then `exe_matchers`.
+    pub fn process_rules(&self) -> Vec<ProcessRule> {
+        let layout_rules = self.layouts.iter().flat_map(|layout| {
+            layout.processes.iter().map(|matcher| ProcessRule {
+                matcher: matcher.clone(),
+                layout: Some(layout.name.clone()),
+            })
+        });
+        let extra_rules = self.exe_matchers.iter().map(|matcher| ProcessRule {
+            matcher: matcher.clone(),
+            layout: None,
+        });
+        layout_rules.chain(extra_rules).collect()
+    }
+}
+
+impl Default for TrackerConfig {
+    fn default() -> Self {
+        Self {
+            layouts: vec![StatsLayout::builtin().clone()],
+            consistency_retries: Some(3),
+            selector: ProcessSelector::default(),
+            exe_matchers: Vec::new(),
+        }
+    }
+}
+
+/// Tracks the attach state and broadcasts every change to it.
+struct StateMachine {
+    tx: Sender<TrackerMessage>,
+    last_broadcast: Instant,
+    state: AttachState,
+    transition: Transition,
+    /// The last error sent with `report`, cleared once a read succeeds.
+    last_error: Option<TrackerError>,
+}
+
+impl StateMachine {
+    fn new(tx: Sender<TrackerMessage>) -> Self {
+        let mut machine = Self {
+            tx,
+            last_broadcast: Instant::now(),
+            state: AttachState::Searching,
+            transition: Transition::now("Looking for Spelunky.exe (Classic)".to_string(), None),
+            last_error: None,
+        };
+        machine.log();
+        machine.broadcast();
+        machine
+    }
+
+    /// Move to `state`. Staying in the same state only updates the reason and error
+    /// when the error changes, so retry loops don't flood clients or the console.
+    fn enter(&mut self, state: AttachState, reason: String, error: Option<TrackerError>) {
+        if state == self.state {
+            if self.transition.error != error {
+                if let Some(err) = &error {
+                    eprintln!("{}", err);
+                }
+                self.transition.reason = reason;
+                self.transition.error = error;
+                self.broadcast();
+            }
+            return;
         }
 
-        if let Err(_err) = tx.send(TrackerMessage::Connecting) {
+        self.state = state;
+        self.transition = Transition::now(reason, error);
+        self.log();
+        self.broadcast();
+    }
+
+    fn fail(&mut self, state: AttachState, err: TrackerError) {
+        self.enter(state, err.to_string(), Some(err));
+    }
+
+    /// Log and send an `Error` without changing state. Like `enter`, repeats of the
+    /// last error are dropped so rejected frames don't flood clients or the console.
+    fn report(&mut self, err: TrackerError) {
+        if self.last_error.as_ref() == Some(&err) {
+            return;
+        }
+        eprintln!("{}", err);
+        self.last_error = Some(err.clone());
+        self.send(TrackerMessage::Error { error: err });
+    }
+
+    /// Forget the last reported error, so the next one is reported even if it repeats.
+    fn clear_error(&mut self) {
+        self.last_error = None;
+    }
+
+    fn log(&self) {
+        match &self.transition.error {
+            Some(err) if err.to_string() != self.transition.reason => {
+                println!("{:?}: {} ({})", self.state, self.transition.reason, err)
+            }
+            _ => println!("{:?}: {}", self.state, self.transition.reason),
+        }
+    }
+
+    /// Resend the current state once a second so clients that connected since hear it.
+    fn heartbeat(&mut self) {
+        if Instant::now() - self.last_broadcast > Duration::from_secs(1) {
+            self.broadcast();
+        }
+    }
+
+    fn broadcast(&mut self) {
+        self.last_broadcast = Instant::now();
+        let transition = self.transition.clone();
+        let msg = match self.state {
+            AttachState::Searching => TrackerMessage::Searching(transition),
+            AttachState::ProcessFound => TrackerMessage::ProcessFound(transition),
+            AttachState::WaitingForGameData => TrackerMessage::WaitingForGameData(transition),
+            AttachState::Attached => TrackerMessage::Attached(transition),
+            AttachState::ProcessExited => TrackerMessage::ProcessExited(transition),
+        };
+        self.send(msg);
+    }
+
+    fn send(&self, msg: TrackerMessage) {
+        if let Err(_err) = self.tx.send(msg) {
             //eprintln!("Message passing backed up... {}", err);
         }
+    }
+}
+
+/// Passes on values that changed, and repeats the last one once a second so clients
+/// that connected since get it too.
+struct ChangeFeed<T> {
+    last: Option<T>,
+    last_update: Instant,
+}
+
+impl<T: Clone + PartialEq> ChangeFeed<T> {
+    fn new() -> Self {
+        Self {
+            last: None,
+            last_update: Instant::now(),
+        }
+    }
+
+    /// The value to send, if any.
+    fn update(&mut self, value: T) -> Option<T> {
+        if self.last.as_ref() == Some(&value)
+            && Instant::now() - self.last_update <= Duration::from_secs(1)
+        {
+            return None;
+        }
+        self.last = Some(value.clone());
+        self.last_update = Instant::now();
+        Some(value)
+    }
+}
+
+/// How many reads in a row can miss game data before an attached tracker goes back
+/// to waiting for it. Single misses happen when the game reallocates its data,
+/// e.g. during level transitions.
+const MAX_MISSED_READS: usize = 10;
+
+/// Whether `err` means the pointer chain isn't set up yet, rather than a bad sample.
+fn is_missing_game_data(err: &TrackerError) -> bool {
+    matches!(
+        err,
+        TrackerError::ReadFailed { .. }
+            | TrackerError::PartialRead { .. }
+            | TrackerError::InvalidPointer { .. }
+    )
+}
+
+/// The layout for a process whose build no layout lists: the one tied to the rule
+/// it matched, otherwise the general fallback.
+fn fallback_layout<'a>(
+    layouts: &'a [StatsLayout],
+    candidate: &ProcessCandidate,
+) -> Option<&'a StatsLayout> {
+    candidate
+        .layout
+        .as_deref()
+        .and_then(|name| StatsLayout::named(layouts, name))
+        .or_else(|| StatsLayout::fallback(layouts))
+}
+
+/// Apply pending client commands. Returns whether the selector changed.
+fn handle_commands(
+    commands: &Receiver<TrackerCommand>,
+    machine: &StateMachine,
+    rules: &[ProcessRule],
+    selector: &mut ProcessSelector,
+) -> bool {
+    let mut changed = false;
+    while let Ok(command) = commands.try_recv() {
+        match command {
+            TrackerCommand::SelectProcess { selector: new } => {
+                if *selector != new {
+                    println!("Selecting process by {:?}", new);
+                    *selector = new;
+                    changed = true;
+                }
+            }
+            TrackerCommand::ListProcesses => match list_game_processes(rules) {
+                Ok(candidates) => machine.send(TrackerMessage::ProcessCandidates {
+                    candidates,
+                    selector: selector.clone(),
+                }),
+                Err(err) => machine.send(TrackerMessage::Error { error: err }),
+            },
+        }
+    }
+    changed
+}
+
+pub fn run_forever(
+    tx: Sender<TrackerMessage>,
+    commands: Receiver<TrackerCommand>,
+    config: TrackerConfig,
+) -> anyhow::Result<()> {
+    let rules = config.process_rules();
+    let layouts = config.layouts;
+    let mut selector = config.selector;
+    let mut machine = StateMachine::new(tx);
+    let mut reported_build = None;
+    let mut reported_candidates = Vec::new();
+    // Kept across attaches so the history survives the game restarting.
+    let mut deaths = DeathTracker::new();
+    loop {
+        machine.heartbeat();
+        handle_commands(&commands, &machine, &rules, &mut selector);
+
+        let candidates = match list_game_processes(&rules) {
+            Ok(candidates) => candidates,
+            Err(err) => {
+                machine.fail(AttachState::Searching, err);
+                sleep(Duration::from_millis(1000));
+                continue;
+            }
+        };
+        if candidates != reported_candidates {
+            machine.send(TrackerMessage::ProcessCandidates {
+                candidates: candidates.clone(),
+                selector: selector.clone(),
+            });
+            reported_candidates = candidates.clone();
+        }
 
         // Try to open process
-        let base_addr;
-        let process = match get_spelunky_process() {
-            Ok(process) => {
-                base_addr = match get_base_addr(process) {
-                    Ok(base_addr) => base_addr,
-                    Err(_err) => {
-                        // eprintln!("Failed to get base addr: {:?}", err);
-                        unsafe {
-                            CloseHandle(process);
-                        };
+        let (process, candidate) = match get_spelunky_process(&candidates, &selector) {
+            Ok(found) => found,
+            Err(err) => {
+                machine.fail(AttachState::Searching, err);
+                sleep(Duration::from_millis(1000));
+                continue;
+            }
+        };
+        let pid = process.pid();
+        let build = process.build();
+
+        let layout = match StatsLayout::select(&layouts, &build) {
+            Some(layout) => layout,
+            None => {
+                let fallback = fallback_layout(&layouts, &candidate);
+                if reported_build != Some(build) {
+                    match fallback {
+                        Some(layout) => eprintln!(
+                            "Unrecognized game build ({}), using layout {:?}",
+                            build, layout.name
+                        ),
+                        None => eprintln!("Unrecognized game build ({}), no layout to use", build),
+                    }
+                    reported_build = Some(build);
+                }
+
+                machine.send(TrackerMessage::UnknownBuild {
+                    build,
+                    fallback: fallback.map(|layout| layout.name.clone()),
+                });
+
+                match fallback {
+                    Some(layout) => layout,
+                    None => {
+                        machine.fail(
+                            AttachState::Searching,
+                            TrackerError::Other {
+                                message: format!("No layout for game build ({})", build),
+                            },
+                        );
                         sleep(Duration::from_millis(1000));
                         continue;
                     }
-                };
-                process
+                }
             }
-            Err(_) => {
+        };
+
+        let layout = match layout.resolved(&process, process.base_addr()) {
+            Ok(layout) => layout,
+            Err(err) => {
+                machine.fail(AttachState::Searching, err.into());
                 sleep(Duration::from_millis(1000));
                 continue;
             }
         };
 
-        connecting = false;
-        println!("Connected!");
-        let mut stats_state = Stats::default();
+        machine.enter(
+            AttachState::ProcessFound,
+            format!("Found process {} using layout {:?}", pid, layout.name),
+            None,
+        );
+
+        let mut stats_state: Option<Stats> = None;
+        let mut force_payload = true;
         let mut last_update = Instant::now();
+        let mut death_history_feed = ChangeFeed::new();
+        let mut completion_feeds: Vec<_> = Ruleset::ALL.iter().map(|_| ChangeFeed::new()).collect();
+        let mut last_completions: Vec<Option<Completion>> = vec![None; Ruleset::ALL.len()];
+        let mut missed_reads = 0;
         loop {
-            let mut exit_code: DWORD = 0;
-            unsafe { GetExitCodeProcess(process, &mut exit_code) };
-            if exit_code != STILL_ACTIVE {
-                eprintln!("Process went away...");
+            if machine.state != AttachState::Attached {
+                machine.heartbeat();
+            }
+
+            if handle_commands(&commands, &machine, &rules, &mut selector) {
+                machine.enter(
+                    AttachState::Searching,
+                    format!("Detached from process {} to switch processes", pid),
+                    None,
+                );
+                break;
+            }
+
+            if process.has_exited() {
+                machine.enter(
+                    AttachState::ProcessExited,
+                    format!("Process {} went away", pid),
+                    None,
+                );
                 sleep(Duration::from_millis(1000));
                 break;
             }
 
-            let new_stats_state = match Stats::from_memory(process, base_addr) {
+            let new_stats_state = match config.consistency_retries {
+                Some(retries) => {
+                    Stats::from_memory_consistent(&process, process.base_addr(), &layout, retries)
+                }
+                None => Stats::from_memory(&process, &layout),
+            };
+            let new_stats_state = match new_stats_state.and_then(|stats| {
+                // Drop implausible frames instead of flashing them on the overlay.
+                stats.validate()?;
+                Ok(stats)
+            }) {
                 Ok(stats_state) => stats_state,
-                Err(_err) => {
-                    // eprintln!("Failed to get base addr: {:?}", err);
-                    // unsafe {
-                    //     CloseHandle(process);
-                    // };
+                // Keep showing the last stats through brief misses once attached.
+                Err(err)
+                    if is_missing_game_data(&err)
+                        && (stats_state.is_none() || missed_reads >= MAX_MISSED_READS) =>
+                {
+                    machine.fail(AttachState::WaitingForGameData, err);
                     sleep(Duration::from_millis(1000));
                     continue;
                 }
+                Err(err) => {
+                    if is_missing_game_data(&err) {
+                        missed_reads += 1;
+                    }
+                    machine.report(err);
+                    sleep(Duration::from_millis(100));
+                    continue;
+                }
             };
+            missed_reads = 0;
+            machine.clear_error();
+
+            if machine.state != AttachState::Attached {
+                machine.enter(
+                    AttachState::Attached,
+                    format!("Reading stats from process {}", pid),
+                    None,
+                );
+                // Make sure the first payload goes out right away.
+                force_payload = true;
+            }
 
-            if stats_state != new_stats_state
+            if force_payload
+                || stats_state.as_ref() != Some(&new_stats_state)
                 || Instant::now() - last_update > Duration::from_secs(1)
             {
-                stats_state = new_stats_state;
+                force_payload = false;
                 last_update = Instant::now();
-                if let Err(_err) = tx.send(TrackerMessage::Payload {
-                    stats: stats_state.clone(),
-                }) {
-                    //eprintln!("Message passing backed up... {}", err);
+                machine.send(TrackerMessage::Payload {
+                    stats: new_stats_state.clone(),
+                });
+            }
+
+            for ((ruleset, feed), last) in Ruleset::ALL
+                .iter()
+                .zip(&mut completion_feeds)
+                .zip(&mut last_completions)
+            {
+                let completion = ruleset.completion(&new_stats_state);
+                // Nothing counts as finished on the first read after attaching.
+                if completion.is_complete() && last.as_ref().is_some_and(|last| !last.is_complete())
+                {
+                    println!("Run complete for ruleset {}", ruleset.number());
+                    machine.send(TrackerMessage::RunComplete { ruleset: *ruleset });
+                }
+                *last = Some(completion.clone());
+                if let Some(completion) = feed.update(completion) {
+                    machine.send(TrackerMessage::Completion { completion });
                 }
             }
 
+            // Nothing counts as changed on the first read after attaching.
+            let delta = stats_state
+                .as_ref()
+                .map(|previous| previous.delta(&new_stats_state))
+                .unwrap_or_default();
+            stats_state = Some(new_stats_state);
+
+            for death in deaths.update(&delta) {
+                println!("Death: {}", death.summary());
+                machine.send(TrackerMessage::Death { death });
+            }
+            if let Some(deaths) = death_history_feed.update(deaths.history()) {
+                machine.send(TrackerMessage::DeathHistory { deaths });
+            }
+            if !delta.is_empty() {
+                machine.send(TrackerMessage::Changes { delta });
+            }
+
             sleep(Duration::from_millis(100));
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::broadcast::{channel, Receiver};
+
+    use super::*;
+
+    /// The state messages sent so far, as `(type, reason)`.
+    fn states(rx: &mut Receiver<TrackerMessage>) -> Vec<(String, String)> {
+        let mut states = Vec::new();
+        while let Ok(msg) = rx.try_recv() {
+            let msg = serde_json::to_value(msg).unwrap();
+            if let Some(reason) = msg["reason"].as_str() {
+                states.push((
+                    msg["type"].as_str().unwrap().to_string(),
+                    reason.to_string(),
+                ));
+            }
+        }
+        states
+    }
+
+    fn errors(rx: &mut Receiver<TrackerMessage>) -> usize {
+        let mut errors = 0;
+        while let Ok(msg) = rx.try_recv() {
+            if matches!(msg, TrackerMessage::Error { .. }) {
+                errors += 1;
+            }
+        }
+        errors
+    }
+
+    fn state(kind: &str, reason: &str) -> (String, String) {
+        (kind.to_string(), reason.to_string())
+    }
+
+    #[test]
+    fn starts_out_searching() {
+        let (tx, mut rx) = channel(16);
+        let _machine = StateMachine::new(tx);
+        assert_eq!(
+            states(&mut rx),
+            [state("Searching", "Looking for Spelunky.exe (Classic)")]
+        );
+    }
+
+    #[test]
+    fn a_new_error_in_the_same_state_replaces_the_reason() {
+        let (tx, mut rx) = channel(16);
+        let mut machine = StateMachine::new(tx);
+        states(&mut rx);
+
+        machine.fail(
+            AttachState::Searching,
+            TrackerError::AccessDenied { pid: 42 },
+        );
+        let denied = TrackerError::AccessDenied { pid: 42 }.to_string();
+        assert_eq!(states(&mut rx), [state("Searching", &denied)]);
+
+        // The same error again is dropped.
+        machine.fail(
+            AttachState::Searching,
+            TrackerError::AccessDenied { pid: 42 },
+        );
+        assert!(states(&mut rx).is_empty());
+
+        machine.fail(AttachState::Searching, TrackerError::ProcessNotFound);
+        assert_eq!(states(&mut rx), [state("Searching", "No process found")]);
+    }
+
+    #[test]
+    fn changing_state_always_broadcasts() {
+        let (tx, mut rx) = channel(16);
+        let mut machine = StateMachine::new(tx);
+        states(&mut rx);
+
+        machine.enter(
+            AttachState::ProcessFound,
+            "Found process 1".to_string(),
+            None,
+        );
+        machine.enter(
+            AttachState::ProcessFound,
+            "Found it again".to_string(),
+            None,
+        );
+        machine.enter(AttachState::Attached, "Attached to 1".to_string(), None);
+        assert_eq!(
+            states(&mut rx),
+            [
+                state("ProcessFound", "Found process 1"),
+                state("Attached", "Attached to 1")
+            ]
+        );
+        assert_eq!(machine.state, AttachState::Attached);
+    }
+
+    #[test]
+    fn reports_each_error_once_until_cleared() {
+        let (tx, mut rx) = channel(16);
+        let mut machine = StateMachine::new(tx);
+        states(&mut rx);
+
+        machine.report(TrackerError::ProcessNotFound);
+        machine.report(TrackerError::ProcessNotFound);
+        assert_eq!(errors(&mut rx), 1);
+
+        machine.report(TrackerError::AccessDenied { pid: 1 });
+        assert_eq!(errors(&mut rx), 1);
+
+        machine.clear_error();
+        machine.report(TrackerError::AccessDenied { pid: 1 });
+        assert_eq!(errors(&mut rx), 1);
+    }
+}
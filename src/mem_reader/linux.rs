@@ -0,0 +1,281 @@
+use std::fs::{self, File};
+use std::io::{Cursor, ErrorKind};
+use std::os::unix::fs::FileExt;
+use std::path::Path;
+
+use super::MemorySource;
+use crate::error::TrackerError;
+use crate::process::{ProcessCandidate, ProcessRule};
+
+pub(crate) type Process = WineProcess;
+
+/// A Wine/Proton process running Spelunky Classic.
+pub(crate) struct WineProcess {
+    pid: u32,
+    /// Path of the game executable in the process's memory map.
+    exe: String,
+    mem: File,
+}
+
+/// File name of `path`. Wine keeps the Windows path as argv[0], so the separator
+/// may be either slash.
+fn file_name(path: &str) -> &str {
+    path.rsplit(['\\', '/']).next().unwrap_or(path)
+}
+
+/// The executable Wine is running in `pid`.
+fn wine_exe(pid: u32) -> Option<String> {
+    exe_from_cmdline(&fs::read(format!("/proc/{}/cmdline", pid)).ok()?)
+}
+
+/// The last non-empty argument of a NUL-separated command line, if it's an `.exe`.
+fn exe_from_cmdline(cmdline: &[u8]) -> Option<String> {
+    let arg = cmdline.split(|b| *b == 0).rfind(|arg| !arg.is_empty())?;
+    let arg = String::from_utf8_lossy(arg).into_owned();
+    arg.to_ascii_lowercase().ends_with(".exe").then_some(arg)
+}
+
+pub(crate) fn list_game_processes(
+    rules: &[ProcessRule],
+) -> Result<Vec<ProcessCandidate>, TrackerError> {
+    let entries = fs::read_dir("/proc").map_err(|err| TrackerError::System {
+        operation: "read_dir(/proc)".to_string(),
+        code: err.raw_os_error().unwrap_or(0),
+    })?;
+
+    let mut candidates = Vec::new();
+    for entry in entries.flatten() {
+        let pid = match entry
+            .file_name()
+            .to_str()
+            .and_then(|s| s.parse::<u32>().ok())
+        {
+            Some(pid) => pid,
+            None => continue,
+        };
+
+        let exe = match wine_exe(pid) {
+            Some(exe) => exe,
+            None => continue,
+        };
+
+        // Launchers like `wine start` share the command line, only the real game maps the exe.
+        let name = file_name(&exe);
+        let path = match find_module(pid, |path| file_name(path).eq_ignore_ascii_case(name)) {
+            Ok((_, path)) => path,
+            Err(_) => continue,
+        };
+
+        // Rules may name either the Windows path Wine was given or the mapped file.
+        let rule = match ProcessRule::find(rules, &exe).or_else(|| ProcessRule::find(rules, &path))
+        {
+            Some(rule) => rule,
+            None => continue,
+        };
+
+        candidates.push(ProcessCandidate {
+            pid,
+            path: Some(path),
+            wine_path: Some(exe),
+            start_time: start_time(pid),
+            layout: rule.layout.clone(),
+        });
+    }
+
+    Ok(candidates)
+}
+
+pub(crate) fn open_process(candidate: &ProcessCandidate) -> Result<WineProcess, TrackerError> {
+    let pid = candidate.pid;
+    let exe = candidate
+        .path
+        .clone()
+        .ok_or(TrackerError::ModuleNotFound { pid })?;
+    let mem = File::open(format!("/proc/{}/mem", pid)).map_err(|err| {
+        if err.kind() == ErrorKind::PermissionDenied {
+            TrackerError::AccessDenied { pid }
+        } else {
+            TrackerError::System {
+                operation: format!("open(/proc/{}/mem)", pid),
+                code: err.raw_os_error().unwrap_or(0),
+            }
+        }
+    })?;
+    Ok(WineProcess { pid, exe, mem })
+}
+
+/// Start time of `pid` in milliseconds since the Unix epoch.
+fn start_time(pid: u32) -> Option<u64> {
+    // The kernel reports process start in clock ticks since boot. USER_HZ is 100
+    // on every architecture Wine runs on.
+    const TICKS_PER_SEC: u64 = 100;
+
+    let start_ticks = start_ticks(&fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?)?;
+    let boot_time = boot_time(&fs::read_to_string("/proc/stat").ok()?)?;
+
+    Some(boot_time * 1000 + start_ticks * 1000 / TICKS_PER_SEC)
+}
+
+/// The `starttime` field of a `/proc/<pid>/stat` line, in clock ticks since boot.
+fn start_ticks(stat: &str) -> Option<u64> {
+    // Skip past the command name, which may contain spaces and parentheses, then
+    // `starttime` is the 20th field after it.
+    let after_comm = &stat[stat.rfind(')')? + 1..];
+    after_comm.split_whitespace().nth(19)?.parse().ok()
+}
+
+/// Boot time in seconds since the Unix epoch, from the `btime` line of `/proc/stat`.
+fn boot_time(stat: &str) -> Option<u64> {
+    stat.lines()
+        .find_map(|line| line.strip_prefix("btime "))?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// Base address and path of the first module mapped into `pid` whose path matches.
+fn find_module(pid: u32, matches: impl Fn(&str) -> bool) -> Result<(usize, String), TrackerError> {
+    let maps = fs::read_to_string(format!("/proc/{}/maps", pid)).map_err(|err| {
+        if err.kind() == ErrorKind::PermissionDenied {
+            TrackerError::AccessDenied { pid }
+        } else {
+            TrackerError::ModuleNotFound { pid }
+        }
+    })?;
+
+    module_in_maps(&maps, matches).ok_or(TrackerError::ModuleNotFound { pid })
+}
+
+/// Base address and path of the first module in a `/proc/<pid>/maps` listing whose
+/// path matches, i.e. the mapping of its first page.
+fn module_in_maps(maps: &str, matches: impl Fn(&str) -> bool) -> Option<(usize, String)> {
+    maps.lines().find_map(|line| {
+        let (start, file_offset, path) = parse_maps_line(line)?;
+        (file_offset == "00000000" && matches(path)).then(|| (start, path.to_string()))
+    })
+}
+
+/// Start address, file offset and path of one maps line, which looks like
+/// `00400000-00401000 r--p 00000000 08:01 1234      /path/to/Spelunky.exe`.
+fn parse_maps_line(line: &str) -> Option<(usize, &str, &str)> {
+    fn field(s: &str) -> Option<(&str, &str)> {
+        let s = s.trim_start();
+        Some(s.split_once(char::is_whitespace).unwrap_or((s, "")))
+            .filter(|(field, _)| !field.is_empty())
+    }
+
+    let (range, rest) = field(line)?;
+    let (_perms, rest) = field(rest)?;
+    let (file_offset, rest) = field(rest)?;
+    let (_dev, rest) = field(rest)?;
+    let (_inode, rest) = field(rest)?;
+    // The path is the rest of the line, spaces and all.
+    let path = rest.trim_start();
+
+    let start = usize::from_str_radix(range.split('-').next()?, 16).ok()?;
+    Some((start, file_offset, path))
+}
+
+pub(crate) fn get_base_addr(process: &WineProcess) -> Result<usize, TrackerError> {
+    find_module(process.pid, |path| path == process.exe).map(|(base_addr, _)| base_addr)
+}
+
+pub(crate) fn process_id(process: &WineProcess) -> u32 {
+    process.pid
+}
+
+pub(crate) fn is_running(process: &WineProcess) -> bool {
+    Path::new(&format!("/proc/{}", process.pid)).exists()
+}
+
+/// Nothing to do: the memory file is closed when the process is dropped.
+pub(crate) fn close_process(_process: &WineProcess) {}
+
+impl MemorySource for WineProcess {
+    fn read_n_bytes(&self, addr: usize, num_bytes: usize) -> Result<Cursor<Vec<u8>>, TrackerError> {
+        let mut buf: Vec<u8> = vec![0; num_bytes];
+        let bytes_read =
+            self.mem
+                .read_at(&mut buf, addr as u64)
+                .map_err(|_| TrackerError::ReadFailed {
+                    addr,
+                    len: num_bytes,
+                    field: None,
+                })?;
+        buf.truncate(bytes_read);
+
+        Ok(Cursor::new(buf))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn takes_the_exe_from_the_end_of_the_command_line() {
+        let exe = |cmdline: &[u8]| exe_from_cmdline(cmdline);
+        assert_eq!(
+            exe(b"C:\\Program Files\\Spelunky\\Spelunky.exe\0").as_deref(),
+            Some("C:\\Program Files\\Spelunky\\Spelunky.exe")
+        );
+        assert_eq!(
+            exe(b"wine\0start\0/home/user/My Games/SPELUNKY.EXE\0\0").as_deref(),
+            Some("/home/user/My Games/SPELUNKY.EXE")
+        );
+        assert_eq!(exe(b"wineserver\0--foreground\0"), None);
+        assert_eq!(exe(b""), None);
+    }
+
+    #[test]
+    fn reads_the_start_time_after_the_command_name() {
+        let rest = "S 1 1234 1234 0 -1 4194560 100 0 0 0 5 3 0 0 20 0 4 0 987654 123 456";
+        let stat = |comm: &str| format!("4321 ({}) {}\n", comm, rest);
+        assert_eq!(start_ticks(&stat("Spelunky.exe")), Some(987654));
+        assert_eq!(start_ticks(&stat("Spelunky (1).exe")), Some(987654));
+        assert_eq!(start_ticks(&stat(") 1 2 3 (")), Some(987654));
+        assert_eq!(start_ticks("4321 (Spelunky.exe) S 1"), None);
+        assert_eq!(start_ticks("garbage"), None);
+    }
+
+    #[test]
+    fn reads_the_boot_time() {
+        let stat = "cpu  1 2 3 4\nintr 5\nbtime 1700000000\nprocesses 6\n";
+        assert_eq!(boot_time(stat), Some(1700000000));
+        assert_eq!(boot_time("cpu  1 2 3 4\n"), None);
+    }
+
+    #[test]
+    fn finds_the_first_page_of_a_module() {
+        let maps = "\
+00010000-00011000 r--p 00000000 00:00 0
+00400000-00401000 r--p 00000000 08:01 1234                       /home/user/My  Games/Spelunky.exe
+00401000-00500000 r-xp 00001000 08:01 1234                       /home/user/My  Games/Spelunky.exe
+7f0000000000-7f0000001000 r--p 00000000 08:01 99                 /usr/lib/wine/ntdll.so
+";
+        let exe = "/home/user/My  Games/Spelunky.exe";
+        assert_eq!(
+            module_in_maps(maps, |path| path == exe),
+            Some((0x400000, exe.to_string()))
+        );
+        assert_eq!(
+            module_in_maps(maps, |path| path.ends_with("ntdll.so")).map(|(start, _)| start),
+            Some(0x7f0000000000)
+        );
+        assert_eq!(
+            module_in_maps(maps, |path| path.ends_with("Game.exe")),
+            None
+        );
+    }
+
+    #[test]
+    fn parses_anonymous_and_malformed_maps_lines() {
+        assert_eq!(
+            parse_maps_line("00010000-00011000 rw-p 00000000 00:00 0"),
+            Some((0x10000, "00000000", ""))
+        );
+        assert_eq!(parse_maps_line("00010000-00011000 rw-p"), None);
+        assert_eq!(parse_maps_line("zzz-00011000 rw-p 00000000 00:00 0"), None);
+        assert_eq!(parse_maps_line(""), None);
+    }
+}
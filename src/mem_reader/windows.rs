@@ -0,0 +1,318 @@
+use std::io::Cursor;
+use std::mem::size_of;
+
+use byteorder::{ByteOrder, LittleEndian};
+use winapi::shared::minwindef::{DWORD, FILETIME, HMODULE, LPCVOID, LPVOID, MAX_PATH};
+use winapi::shared::ntdef::HANDLE;
+use winapi::shared::winerror::ERROR_ACCESS_DENIED;
+use winapi::um::handleapi::{CloseHandle, INVALID_HANDLE_VALUE};
+use winapi::um::memoryapi::ReadProcessMemory;
+use winapi::um::minwinbase::STILL_ACTIVE;
+use winapi::um::processthreadsapi::{
+    GetExitCodeProcess, GetProcessId, GetProcessTimes, OpenProcess,
+};
+use winapi::um::psapi::{EnumProcessModules, GetModuleFileNameExA};
+use winapi::um::tlhelp32::{
+    CreateToolhelp32Snapshot, Process32First, Process32Next, PROCESSENTRY32, TH32CS_SNAPPROCESS,
+};
+use winapi::um::winbase::QueryFullProcessImageNameA;
+use winapi::um::winnt::{
+    PROCESS_QUERY_INFORMATION, PROCESS_QUERY_LIMITED_INFORMATION, PROCESS_VM_READ,
+};
+
+use super::MemorySource;
+use crate::error::TrackerError;
+use crate::process::{ExePattern, ProcessCandidate, ProcessRule};
+
+pub(crate) type Process = HANDLE;
+
+pub(crate) fn list_game_processes(
+    rules: &[ProcessRule],
+) -> Result<Vec<ProcessCandidate>, TrackerError> {
+    let mut candidates = Vec::new();
+    unsafe {
+        let process_snap = CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0);
+
+        let mut process: PROCESSENTRY32 = PROCESSENTRY32 {
+            dwSize: size_of::<PROCESSENTRY32>()
+                .try_into()
+                .expect("Failed to get size of PROCESSENTRY32"),
+            cntUsage: 0,
+            th32ProcessID: 0,
+            th32DefaultHeapID: 0,
+            th32ModuleID: 0,
+            cntThreads: 0,
+            th32ParentProcessID: 0,
+            pcPriClassBase: 0,
+            dwFlags: 0,
+            szExeFile: [0; MAX_PATH],
+        };
+
+        if process_snap == INVALID_HANDLE_VALUE {
+            return Err(TrackerError::last_os_error("CreateToolhelp32Snapshot"));
+        }
+
+        if Process32First(process_snap, &mut process) == 0 {
+            let err = TrackerError::last_os_error("Process32First");
+            CloseHandle(process_snap);
+            return Err(err);
+        }
+
+        loop {
+            let name_len = process
+                .szExeFile
+                .iter()
+                .position(|c| *c == 0)
+                .unwrap_or(MAX_PATH);
+            let name: Vec<u8> = process.szExeFile[..name_len]
+                .iter()
+                .map(|c| *c as u8)
+                .collect();
+            let name = String::from_utf8_lossy(&name);
+
+            // Only query processes that could match, and prefer the full path when
+            // we're allowed to read it.
+            if ProcessRule::find(rules, &name).is_some() || has_path_rule(rules) {
+                let mut candidate = describe_process(process.th32ProcessID);
+                let rule = match &candidate.path {
+                    Some(path) => ProcessRule::find(rules, path),
+                    None => ProcessRule::find(rules, &name),
+                };
+                if let Some(rule) = rule {
+                    candidate.layout = rule.layout.clone();
+                    candidates.push(candidate);
+                }
+            }
+
+            if Process32Next(process_snap, &mut process) == 0 {
+                CloseHandle(process_snap);
+                break;
+            }
+        }
+    }
+
+    Ok(candidates)
+}
+
+/// Whether any rule needs the full path to decide, rather than just the file name.
+fn has_path_rule(rules: &[ProcessRule]) -> bool {
+    rules.iter().any(|rule| match &rule.matcher.pattern {
+        ExePattern::Name(_) => false,
+        ExePattern::Glob(pattern) => pattern.contains(['\\', '/']),
+        ExePattern::Path(_) => true,
+    })
+}
+
+/// Look up the image path and start time of `pid`, leaving out whatever we
+/// aren't allowed to query.
+fn describe_process(pid: u32) -> ProcessCandidate {
+    let mut candidate = ProcessCandidate {
+        pid,
+        path: None,
+        wine_path: None,
+        start_time: None,
+        layout: None,
+    };
+
+    unsafe {
+        let handle = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+        if handle == winapi::shared::ntdef::NULL {
+            return candidate;
+        }
+
+        let mut path = [0; MAX_PATH];
+        let mut path_len = MAX_PATH as DWORD;
+        if QueryFullProcessImageNameA(handle, 0, path.as_mut_ptr(), &mut path_len) != 0 {
+            let path: Vec<u8> = path[..path_len as usize].iter().map(|c| *c as u8).collect();
+            candidate.path = Some(String::from_utf8_lossy(&path).into_owned());
+        }
+
+        let mut creation: FILETIME = std::mem::zeroed();
+        let mut exit: FILETIME = std::mem::zeroed();
+        let mut kernel: FILETIME = std::mem::zeroed();
+        let mut user: FILETIME = std::mem::zeroed();
+        if GetProcessTimes(handle, &mut creation, &mut exit, &mut kernel, &mut user) != 0 {
+            // FILETIME counts 100ns intervals since 1601-01-01.
+            let intervals =
+                ((creation.dwHighDateTime as u64) << 32) | creation.dwLowDateTime as u64;
+            candidate.start_time = intervals
+                .checked_sub(116_444_736_000_000_000)
+                .map(|since_epoch| since_epoch / 10_000);
+        }
+
+        CloseHandle(handle);
+    }
+
+    candidate
+}
+
+pub(crate) fn open_process(candidate: &ProcessCandidate) -> Result<HANDLE, TrackerError> {
+    let pid = candidate.pid;
+    unsafe {
+        let process_handle = OpenProcess(PROCESS_QUERY_INFORMATION | PROCESS_VM_READ, 0, pid);
+
+        if process_handle == winapi::shared::ntdef::NULL {
+            let err = std::io::Error::last_os_error();
+            if err.raw_os_error() == Some(ERROR_ACCESS_DENIED as i32) {
+                return Err(TrackerError::AccessDenied { pid });
+            }
+            return Err(TrackerError::System {
+                operation: "OpenProcess".to_string(),
+                code: err.raw_os_error().unwrap_or(0),
+            });
+        }
+
+        Ok(process_handle)
+    }
+}
+
+pub(crate) fn get_base_addr(process: &HANDLE) -> Result<usize, TrackerError> {
+    let process = *process;
+    let pid = unsafe { GetProcessId(process) };
+
+    // Get Module name of EXE
+    let mut process_image_filename = [0; MAX_PATH];
+    unsafe {
+        let result = GetModuleFileNameExA(
+            process,
+            0 as HMODULE,
+            process_image_filename.as_mut_ptr(),
+            MAX_PATH as u32,
+        );
+        if result == 0 {
+            return Err(TrackerError::ModuleNotFound { pid });
+        }
+    }
+
+    // Get handles for all modules in process.
+    let mut module_handles: [HMODULE; 1024] = [0 as HMODULE; 1024];
+    let hmodule_size: usize = size_of::<HMODULE>()
+        .try_into()
+        .expect("Failed to get size of HMODULE");
+    let mut bytes_written = 0;
+
+    unsafe {
+        let result = EnumProcessModules(
+            process,
+            module_handles.as_mut_ptr(),
+            size_of::<[HMODULE; 1024]>()
+                .try_into()
+                .expect("Failed to get size for modules"),
+            &mut bytes_written,
+        );
+        if result == 0 {
+            return Err(TrackerError::last_os_error("EnumProcessModules"));
+        }
+    }
+
+    let num_modules = bytes_written as usize / hmodule_size;
+
+    // Enumerate Modules to find handle for EXE module
+    for idx in 0..num_modules {
+        let mut module_filename = [0; MAX_PATH];
+        unsafe {
+            let result = GetModuleFileNameExA(
+                process,
+                module_handles[idx],
+                module_filename.as_mut_ptr(),
+                MAX_PATH as u32,
+            );
+            if result == 0 {
+                continue;
+            }
+        }
+
+        if module_filename != process_image_filename {
+            continue;
+        }
+
+        // Found the exe module base address
+        return Ok(module_handles[idx] as usize);
+    }
+
+    Err(TrackerError::ModuleNotFound { pid })
+}
+
+pub(crate) fn read_uptr32_t(process: HANDLE, addr: usize) -> Result<u32, TrackerError> {
+    let mut buf: [u8; 4] = [0; 4];
+    let mut bytes_read = 0;
+    unsafe {
+        if ReadProcessMemory(
+            process,
+            addr as LPCVOID,
+            buf.as_mut_ptr() as LPVOID,
+            4,
+            &mut bytes_read,
+        ) == 0
+        {
+            return Err(TrackerError::ReadFailed {
+                addr,
+                len: 4,
+                field: None,
+            });
+        }
+    };
+    if bytes_read < 4 {
+        return Err(TrackerError::PartialRead {
+            addr,
+            requested: 4,
+            read: bytes_read,
+            field: None,
+        });
+    }
+    Ok(LittleEndian::read_u32(&buf))
+}
+
+pub(crate) fn read_n_bytes(
+    process: HANDLE,
+    addr: usize,
+    num_bytes: usize,
+) -> Result<Cursor<Vec<u8>>, TrackerError> {
+    let mut buf: Vec<u8> = Vec::with_capacity(num_bytes);
+    let mut bytes_read = 0;
+    unsafe {
+        if ReadProcessMemory(
+            process,
+            addr as LPCVOID,
+            buf.as_mut_ptr() as LPVOID,
+            num_bytes,
+            &mut bytes_read,
+        ) == 0
+        {
+            return Err(TrackerError::ReadFailed {
+                addr,
+                len: num_bytes,
+                field: None,
+            });
+        }
+        buf.set_len(bytes_read);
+    };
+
+    Ok(Cursor::new(buf))
+}
+
+impl MemorySource for HANDLE {
+    fn read_uptr32_t(&self, addr: usize) -> Result<u32, TrackerError> {
+        read_uptr32_t(*self, addr)
+    }
+
+    fn read_n_bytes(&self, addr: usize, num_bytes: usize) -> Result<Cursor<Vec<u8>>, TrackerError> {
+        read_n_bytes(*self, addr, num_bytes)
+    }
+}
+
+pub(crate) fn process_id(process: &HANDLE) -> u32 {
+    unsafe { GetProcessId(*process) }
+}
+
+pub(crate) fn is_running(process: &HANDLE) -> bool {
+    let mut exit_code: DWORD = 0;
+    unsafe { GetExitCodeProcess(*process, &mut exit_code) };
+    exit_code == STILL_ACTIVE
+}
+
+pub(crate) fn close_process(process: &HANDLE) {
+    unsafe {
+        CloseHandle(*process);
+    };
+}
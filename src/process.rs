@@ -0,0 +1,370 @@
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+
+/// A running copy of the game that the tracker could attach to.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ProcessCandidate {
+    pub pid: u32,
+    /// Full path of the executable, if it could be queried.
+    pub path: Option<String>,
+    /// The Windows path Wine was given, when running under Wine.
+    pub wine_path: Option<String>,
+    /// When the process started, in milliseconds since the Unix epoch.
+    pub start_time: Option<u64>,
+    /// Layout tied to the rule the process matched, if any.
+    pub layout: Option<String>,
+}
+
+/// What a `ProcessRule` compares against.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExePattern {
+    /// The executable's file name, e.g. `"Spelunky.exe"`.
+    Name(String),
+    /// A file name pattern with `*` and `?` wildcards. Patterns containing a path
+    /// separator are matched against the full path instead.
+    Glob(String),
+    /// The full path of the executable.
+    Path(String),
+}
+
+/// Which executables count as the game.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct ExeMatcher {
+    #[serde(flatten)]
+    pub pattern: ExePattern,
+    #[serde(default)]
+    pub ignore_case: bool,
+}
+
+impl ExeMatcher {
+    /// Whether the executable at `path` matches. Either slash is accepted as a
+    /// separator, since Wine reports Windows paths.
+    pub fn matches(&self, path: &str) -> bool {
+        let path = normalize(path);
+        let name = path.rsplit('\\').next().unwrap_or(&path);
+        match &self.pattern {
+            ExePattern::Name(expected) => self.eq(name, expected),
+            ExePattern::Glob(pattern) if pattern.contains(['\\', '/']) => {
+                self.glob(&path, &normalize(pattern))
+            }
+            ExePattern::Glob(pattern) => self.glob(name, pattern),
+            ExePattern::Path(expected) => self.eq(&path, &normalize(expected)),
+        }
+    }
+
+    fn eq(&self, a: &str, b: &str) -> bool {
+        if self.ignore_case {
+            a.eq_ignore_ascii_case(b)
+        } else {
+            a == b
+        }
+    }
+
+    fn glob(&self, text: &str, pattern: &str) -> bool {
+        if self.ignore_case {
+            glob_matches(&text.to_ascii_lowercase(), &pattern.to_ascii_lowercase())
+        } else {
+            glob_matches(text, pattern)
+        }
+    }
+}
+
+fn normalize(path: &str) -> String {
+    path.replace('/', "\\")
+}
+
+fn glob_matches(text: &str, pattern: &str) -> bool {
+    let text: Vec<char> = text.chars().collect();
+    let pattern: Vec<char> = pattern.chars().collect();
+
+    // Backtrack to just after the last `*` whenever the rest fails to match.
+    let (mut t, mut p) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == text[t] => {
+                t += 1;
+                p += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    star = Some((star_p, star_t + 1));
+                    p = star_p + 1;
+                    t = star_t + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// An `ExeMatcher`, optionally tied to the layout to read matching processes with.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProcessRule {
+    pub matcher: ExeMatcher,
+    pub layout: Option<String>,
+}
+
+impl ProcessRule {
+    /// The first rule matching the executable at `path`.
+    pub fn find<'a>(rules: &'a [ProcessRule], path: &str) -> Option<&'a ProcessRule> {
+        rules.iter().find(|rule| rule.matcher.matches(path))
+    }
+}
+
+/// Which process to attach to when more than one copy of the game is running.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "policy")]
+pub enum ProcessSelector {
+    /// Whichever the OS lists first.
+    #[default]
+    First,
+    /// The most recently started.
+    Newest,
+    Pid {
+        pid: u32,
+    },
+    /// The process running the executable at `path`, compared case-insensitively. Under Wine
+    /// this may be either the Windows path or the Unix path it's mapped to.
+    Path {
+        path: String,
+    },
+    /// Wait for a client to choose when there's more than one candidate.
+    Prompt,
+}
+
+impl ProcessSelector {
+    pub fn choose<'a>(&self, candidates: &'a [ProcessCandidate]) -> Option<&'a ProcessCandidate> {
+        match self {
+            ProcessSelector::First => candidates.first(),
+            ProcessSelector::Newest => candidates
+                .iter()
+                .max_by_key(|candidate| candidate.start_time.unwrap_or(0)),
+            ProcessSelector::Pid { pid } => candidates.iter().find(|c| c.pid == *pid),
+            ProcessSelector::Path { path } => candidates.iter().find(|c| {
+                [&c.path, &c.wine_path]
+                    .into_iter()
+                    .flatten()
+                    .any(|candidate| candidate.eq_ignore_ascii_case(path))
+            }),
+            ProcessSelector::Prompt if candidates.len() == 1 => candidates.first(),
+            ProcessSelector::Prompt => None,
+        }
+    }
+}
+
+impl FromStr for ProcessSelector {
+    type Err = anyhow::Error;
+
+    /// Parse `first`, `newest`, `prompt`, `pid:<pid>` or `path:<path>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("pid", pid)) => Ok(ProcessSelector::Pid { pid: pid.parse()? }),
+            Some(("path", path)) => Ok(ProcessSelector::Path {
+                path: path.to_string(),
+            }),
+            _ => match s {
+                "first" => Ok(ProcessSelector::First),
+                "newest" => Ok(ProcessSelector::Newest),
+                "prompt" => Ok(ProcessSelector::Prompt),
+                _ => Err(anyhow!("Unknown process selector {:?}", s)),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matcher(pattern: ExePattern, ignore_case: bool) -> ExeMatcher {
+        ExeMatcher {
+            pattern,
+            ignore_case,
+        }
+    }
+
+    fn glob(pattern: &str) -> ExeMatcher {
+        matcher(ExePattern::Glob(pattern.to_string()), false)
+    }
+
+    #[test]
+    fn globs_match_wildcards() {
+        assert!(glob_matches("Spelunky.exe", "Spelunky*.exe"));
+        assert!(glob_matches("SpelunkyClassic.exe", "Spelunky*.exe"));
+        assert!(glob_matches("Spelunky1.exe", "Spelunky?.exe"));
+        assert!(!glob_matches("Spelunky.exe", "Spelunky?.exe"));
+        assert!(glob_matches("a.b.exe", "*.exe"));
+        assert!(glob_matches("Spelunky.exe", "*"));
+        assert!(glob_matches("aab", "*ab"));
+        assert!(!glob_matches("Spelunky.exe.bak", "*.exe"));
+        assert!(!glob_matches("", "?"));
+    }
+
+    #[test]
+    fn ignore_case_applies_to_every_pattern() {
+        let path = r"C:\Games\SPELUNKY.EXE";
+        for pattern in [
+            ExePattern::Name("Spelunky.exe".to_string()),
+            ExePattern::Glob("spel*.exe".to_string()),
+            ExePattern::Path(r"c:\games\spelunky.exe".to_string()),
+        ] {
+            assert!(
+                matcher(pattern.clone(), true).matches(path),
+                "{:?}",
+                pattern
+            );
+            assert!(
+                !matcher(pattern.clone(), false).matches(path),
+                "{:?}",
+                pattern
+            );
+        }
+    }
+
+    #[test]
+    fn globs_without_a_separator_only_see_the_file_name() {
+        assert!(glob("Spelunky*.exe").matches(r"C:\Spelunky Games\Spelunky.exe"));
+        assert!(!glob("*Games*").matches(r"C:\Spelunky Games\Spelunky.exe"));
+    }
+
+    #[test]
+    fn globs_with_a_separator_see_the_whole_path() {
+        let spelunky = glob(r"C:\Games\*\Spelunky.exe");
+        assert!(spelunky.matches(r"C:\Games\Classic\Spelunky.exe"));
+        assert!(!spelunky.matches(r"D:\Games\Classic\Spelunky.exe"));
+        assert!(glob("C:/Games/*/Spelunky.exe").matches(r"C:\Games\Classic\Spelunky.exe"));
+    }
+
+    #[test]
+    fn wine_unix_paths_match_like_windows_paths() {
+        let unix = "/home/user/.wine/drive_c/Games/Spelunky.exe";
+        assert!(matcher(ExePattern::Name("Spelunky.exe".to_string()), false).matches(unix));
+        assert!(glob("Spelunky*.exe").matches(unix));
+        assert!(glob("*/drive_c/Games/*.exe").matches(unix));
+        assert!(matcher(ExePattern::Path(unix.to_string()), false).matches(unix));
+        // A Windows path names a different file than the Unix path it's mapped to.
+        assert!(!matcher(
+            ExePattern::Path(r"C:\Games\Spelunky.exe".to_string()),
+            false
+        )
+        .matches(unix));
+    }
+
+    #[test]
+    fn rules_are_tried_in_order() {
+        let rule = |pattern: &str, layout: &str| ProcessRule {
+            matcher: glob(pattern),
+            layout: Some(layout.to_string()),
+        };
+        let rules = [rule("Spelunky.exe", "exact"), rule("Spelunky*.exe", "any")];
+        let layout = |path| ProcessRule::find(&rules, path).and_then(|rule| rule.layout.clone());
+        assert_eq!(layout(r"C:\Spelunky.exe").as_deref(), Some("exact"));
+        assert_eq!(layout(r"C:\SpelunkyHD.exe").as_deref(), Some("any"));
+        assert_eq!(layout(r"C:\Game.exe"), None);
+    }
+
+    fn candidate(pid: u32, path: &str, start_time: Option<u64>) -> ProcessCandidate {
+        ProcessCandidate {
+            pid,
+            path: Some(path.to_string()),
+            wine_path: None,
+            start_time,
+            layout: None,
+        }
+    }
+
+    fn chosen(selector: &ProcessSelector, candidates: &[ProcessCandidate]) -> Option<u32> {
+        selector.choose(candidates).map(|candidate| candidate.pid)
+    }
+
+    #[test]
+    fn newest_prefers_processes_with_a_known_start_time() {
+        let candidates = [
+            candidate(1, "a.exe", Some(200)),
+            candidate(2, "b.exe", None),
+            candidate(3, "c.exe", Some(100)),
+        ];
+        assert_eq!(chosen(&ProcessSelector::Newest, &candidates), Some(1));
+        assert_eq!(chosen(&ProcessSelector::Newest, &candidates[1..2]), Some(2));
+        assert_eq!(chosen(&ProcessSelector::Newest, &[]), None);
+    }
+
+    #[test]
+    fn prompt_only_chooses_a_lone_candidate() {
+        let candidates = [candidate(1, "a.exe", None), candidate(2, "b.exe", None)];
+        assert_eq!(chosen(&ProcessSelector::Prompt, &candidates[..1]), Some(1));
+        assert_eq!(chosen(&ProcessSelector::Prompt, &candidates), None);
+        assert_eq!(chosen(&ProcessSelector::Prompt, &[]), None);
+    }
+
+    #[test]
+    fn chooses_by_pid_and_path() {
+        let candidates = [
+            candidate(1, r"C:\Games\Spelunky.exe", None),
+            candidate(2, r"D:\Spelunky.exe", None),
+        ];
+        assert_eq!(
+            chosen(&ProcessSelector::Pid { pid: 2 }, &candidates),
+            Some(2)
+        );
+        assert_eq!(chosen(&ProcessSelector::Pid { pid: 3 }, &candidates), None);
+        let path = |path: &str| ProcessSelector::Path {
+            path: path.to_string(),
+        };
+        assert_eq!(
+            chosen(&path(r"c:\games\spelunky.exe"), &candidates),
+            Some(1)
+        );
+        assert_eq!(chosen(&path(r"C:\Spelunky.exe"), &candidates), None);
+    }
+
+    #[test]
+    fn paths_match_either_side_of_wine() {
+        let candidates = [ProcessCandidate {
+            wine_path: Some(r"C:\Games\Spelunky.exe".to_string()),
+            ..candidate(1, "/home/user/.wine/drive_c/Games/Spelunky.exe", None)
+        }];
+        let path = |path: &str| ProcessSelector::Path {
+            path: path.to_string(),
+        };
+        assert_eq!(
+            chosen(&path(r"C:\Games\Spelunky.exe"), &candidates),
+            Some(1)
+        );
+        assert_eq!(
+            chosen(
+                &path("/home/user/.wine/drive_c/Games/Spelunky.exe"),
+                &candidates
+            ),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn parses_selectors() {
+        let parse = |s: &str| s.parse::<ProcessSelector>().ok();
+        assert_eq!(parse("first"), Some(ProcessSelector::First));
+        assert_eq!(parse("newest"), Some(ProcessSelector::Newest));
+        assert_eq!(parse("prompt"), Some(ProcessSelector::Prompt));
+        assert_eq!(parse("pid:1234"), Some(ProcessSelector::Pid { pid: 1234 }));
+        assert_eq!(parse("pid:abc"), None);
+        // Only the first colon separates the policy, so drive letters survive.
+        assert_eq!(
+            parse(r"path:C:\Games\Spelunky.exe"),
+            Some(ProcessSelector::Path {
+                path: r"C:\Games\Spelunky.exe".to_string()
+            })
+        );
+        assert_eq!(parse("oldest"), None);
+        assert_eq!(parse("size:10"), None);
+    }
+}
@@ -0,0 +1,182 @@
+use serde::Serialize;
+
+use crate::stats::{Category, StatKey, Stats};
+
+/// Counters neither ruleset asks for, because the death can't reasonably be
+/// collected. The overlay leaves them out too.
+const NOT_REQUIRED: &[&str] = &[
+    "misc_deaths.boulder",
+    "enemy_deaths.bat",
+    "enemy_deaths.zombie",
+    "enemy_deaths.vampire",
+    "enemy_deaths.megamouth",
+];
+
+/// An All Stats Entry ruleset. Both need every general counter, every level death
+/// and every misc death; they differ in what counts for an enemy.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(into = "u8")]
+pub enum Ruleset {
+    /// Ruleset 1: every enemy has to be killed and has to kill the player.
+    KillsAndDeaths,
+    /// Ruleset 2: killing an enemy or being killed by it is enough.
+    KillOrDeath,
+}
+
+impl From<Ruleset> for u8 {
+    fn from(ruleset: Ruleset) -> u8 {
+        ruleset.number()
+    }
+}
+
+/// Something a ruleset asks for, done once any of its counters is above zero.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Requirement {
+    /// Display name from the stat catalog, e.g. `"Giant Spider"`.
+    pub name: &'static str,
+    pub keys: Vec<&'static str>,
+}
+
+impl Requirement {
+    pub fn is_met(&self, stats: &Stats) -> bool {
+        self.keys
+            .iter()
+            .any(|key| stats.get(key).is_some_and(|value| value > 0.0))
+    }
+}
+
+/// How far a set of stats is through a ruleset.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Completion {
+    pub ruleset: Ruleset,
+    pub done: usize,
+    pub total: usize,
+    /// Requirements not met yet, in stat catalog order.
+    pub missing: Vec<Requirement>,
+}
+
+impl Completion {
+    pub fn is_complete(&self) -> bool {
+        self.missing.is_empty()
+    }
+
+    pub fn pprint(&self) {
+        println!(
+            "Ruleset {}: {} of {} done",
+            self.ruleset.number(),
+            self.done,
+            self.total
+        );
+        for requirement in &self.missing {
+            println!("  Missing: {}", requirement.keys.join(" or "));
+        }
+    }
+}
+
+impl Ruleset {
+    pub const ALL: &'static [Ruleset] = &[Ruleset::KillsAndDeaths, Ruleset::KillOrDeath];
+
+    /// The number the overlay uses for this ruleset in `?ruleset=`.
+    pub fn number(self) -> u8 {
+        match self {
+            Ruleset::KillsAndDeaths => 1,
+            Ruleset::KillOrDeath => 2,
+        }
+    }
+
+    /// Everything this ruleset asks for, in stat catalog order.
+    pub fn requirements(self) -> Vec<Requirement> {
+        let mut requirements: Vec<Requirement> = Vec::new();
+        for stat in StatKey::ALL {
+            if NOT_REQUIRED.contains(&stat.key) {
+                continue;
+            }
+            let enemy = matches!(stat.category, Category::EnemyDeaths | Category::EnemyKills);
+            if enemy && self == Ruleset::KillOrDeath {
+                // Kills and deaths of the same enemy make one requirement.
+                if let Some(requirement) = requirements.iter_mut().find(|requirement| {
+                    requirement.name == stat.name && requirement.keys[0].starts_with("enemy_")
+                }) {
+                    requirement.keys.push(stat.key);
+                    continue;
+                }
+            }
+            requirements.push(Requirement {
+                name: stat.name,
+                keys: vec![stat.key],
+            });
+        }
+        requirements
+    }
+
+    pub fn completion(self, stats: &Stats) -> Completion {
+        let requirements = self.requirements();
+        let total = requirements.len();
+        let missing: Vec<_> = requirements
+            .into_iter()
+            .filter(|requirement| !requirement.is_met(stats))
+            .collect();
+        Completion {
+            ruleset: self,
+            done: total - missing.len(),
+            total,
+            missing,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(ruleset: Ruleset, name: &str) -> Vec<&'static str> {
+        let requirements: Vec<_> = ruleset
+            .requirements()
+            .into_iter()
+            .filter(|requirement| requirement.name == name)
+            .collect();
+        assert_eq!(requirements.len(), 1, "{} in ruleset {:?}", name, ruleset);
+        requirements[0].keys.clone()
+    }
+
+    #[test]
+    fn requirement_totals() {
+        assert_eq!(Ruleset::KillsAndDeaths.requirements().len(), 84);
+        assert_eq!(Ruleset::KillOrDeath.requirements().len(), 66);
+    }
+
+    #[test]
+    fn one_sided_enemies_need_their_only_counter() {
+        for &ruleset in Ruleset::ALL {
+            assert_eq!(keys(ruleset, "Monkey"), ["enemy_kills.monkey"]);
+            assert_eq!(keys(ruleset, "Magma Man"), ["enemy_deaths.magma_man"]);
+            assert_eq!(keys(ruleset, "Ghost"), ["enemy_deaths.ghost"]);
+        }
+    }
+
+    #[test]
+    fn bats_only_need_a_kill() {
+        for &ruleset in Ruleset::ALL {
+            assert_eq!(keys(ruleset, "Bat"), ["enemy_kills.bat"]);
+        }
+    }
+
+    #[test]
+    fn either_counter_meets_a_kill_or_death_requirement() {
+        let mut stats = Stats::default();
+        stats.enemy_deaths.spider = 1.0;
+        let missing = |ruleset: Ruleset| {
+            ruleset
+                .completion(&stats)
+                .missing
+                .iter()
+                .any(|requirement| requirement.name == "Spider")
+        };
+        assert!(!missing(Ruleset::KillOrDeath));
+        assert!(missing(Ruleset::KillsAndDeaths));
+
+        let completion = Ruleset::KillOrDeath.completion(&stats);
+        assert_eq!(completion.done, 1);
+        assert_eq!(completion.total, 66);
+    }
+}
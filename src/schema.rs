@@ -0,0 +1,89 @@
+use anyhow::anyhow;
+use serde_json::{Map, Value};
+
+use crate::stats::Stats;
+
+/// Version of the serialized `Stats` layout, written to `Stats::schema_version`.
+///
+/// Counters that are only added don't need a new version, since missing counters
+/// load as zero. Bump it, with a migration, when older stats need fixing up to
+/// mean the same thing, e.g. a counter that is renamed, split or counted differently.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// `MIGRATIONS[n]` turns stats of version `n` into version `n + 1`.
+const MIGRATIONS: &[fn(&mut Map<String, Value>)] = &[
+    // Stats serialized before versioning have the same fields as version 1.
+    |_| {},
+];
+
+const _: () = assert!(MIGRATIONS.len() == SCHEMA_VERSION as usize);
+
+impl Stats {
+    /// Load serialized stats of any schema version, migrating older ones to the
+    /// current layout. Deserializing `Stats` directly skips the migrations.
+    pub fn from_json(mut value: Value) -> anyhow::Result<Stats> {
+        let object = value
+            .as_object_mut()
+            .ok_or_else(|| anyhow!("Stats must be a JSON object"))?;
+        let version = match object.get("schema_version") {
+            None => 0,
+            Some(version) => version
+                .as_u64()
+                .and_then(|version| u32::try_from(version).ok())
+                .ok_or_else(|| anyhow!("Invalid stats schema version {}", version))?,
+        };
+        if version > SCHEMA_VERSION {
+            return Err(anyhow!(
+                "Stats schema version {} is newer than the supported version {}",
+                version,
+                SCHEMA_VERSION
+            ));
+        }
+
+        for migration in &MIGRATIONS[version as usize..] {
+            migration(object);
+        }
+        object.insert("schema_version".to_string(), SCHEMA_VERSION.into());
+        Ok(serde_json::from_value(value)?)
+    }
+
+    pub fn from_json_str(json: &str) -> anyhow::Result<Stats> {
+        Self::from_json(serde_json::from_str(json)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn migrates_unversioned_stats() {
+        let stats = Stats::from_json(json!({
+            "crates_opened": 12.0,
+            "enemy_kills": { "spider": 3.0 },
+        }))
+        .unwrap();
+        assert_eq!(stats.schema_version, SCHEMA_VERSION);
+        assert_eq!(stats.crates_opened, 12.0);
+        assert_eq!(stats.enemy_kills.spider, 3.0);
+        assert_eq!(stats.enemy_kills.bat, 0.0);
+        assert_eq!(stats.level_deaths.level_1, 0.0);
+    }
+
+    #[test]
+    fn round_trips_current_stats() {
+        let mut stats = Stats::default();
+        stats.misc_deaths.spikes = 4.0;
+        let json = serde_json::to_string(&stats).unwrap();
+        assert_eq!(Stats::from_json_str(&json).unwrap(), stats);
+    }
+
+    #[test]
+    fn rejects_newer_and_invalid_versions() {
+        assert!(Stats::from_json(json!({ "schema_version": SCHEMA_VERSION + 1 })).is_err());
+        assert!(Stats::from_json(json!({ "schema_version": "one" })).is_err());
+        assert!(Stats::from_json(json!([])).is_err());
+    }
+}
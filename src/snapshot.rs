@@ -0,0 +1,314 @@
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Cursor, Read, Write};
+use std::path::Path;
+
+use anyhow::anyhow;
+use byteorder::{ReadBytesExt, WriteBytesExt, LE};
+
+use crate::error::TrackerError;
+use crate::game_build::BuildFingerprint;
+use crate::layout::StatsLayout;
+use crate::mem_reader::MemorySource;
+use crate::stats::Stats;
+
+static MAGIC: &[u8; 4] = b"CSTS";
+
+/// Current version of the snapshot file format.
+///
+/// Layout (all integers little-endian):
+///   magic "CSTS", u32 version, u64 base address, u32 region count,
+///   then per region: u64 address, u32 length, `length` bytes.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Region {
+    pub addr: usize,
+    pub bytes: Vec<u8>,
+}
+
+/// Recorded game memory that can be replayed through `MemorySource`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Snapshot {
+    pub base_addr: usize,
+    pub regions: Vec<Region>,
+}
+
+impl Snapshot {
+    /// Follow the stats pointer chain in `process` and keep every region it touches,
+    /// along with the module header so the build can be identified on replay.
+    pub fn capture<M: MemorySource + ?Sized>(
+        process: &M,
+        base_addr: usize,
+        layout: &StatsLayout,
+    ) -> anyhow::Result<Self> {
+        let recorder = RecordingSource {
+            inner: process,
+            regions: RefCell::new(Vec::new()),
+        };
+        BuildFingerprint::read(&recorder, base_addr)?;
+        let layout = layout.resolved(&recorder, base_addr)?;
+        Stats::read(&recorder, base_addr, &layout)?;
+
+        Ok(Self {
+            base_addr,
+            regions: merge_regions(recorder.regions.into_inner()),
+        })
+    }
+
+    pub fn write_to<W: Write>(&self, mut writer: W) -> anyhow::Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_u32::<LE>(SNAPSHOT_VERSION)?;
+        writer.write_u64::<LE>(self.base_addr as u64)?;
+        writer.write_u32::<LE>(self.regions.len().try_into()?)?;
+        for region in &self.regions {
+            writer.write_u64::<LE>(region.addr as u64)?;
+            writer.write_u32::<LE>(region.bytes.len().try_into()?)?;
+            writer.write_all(&region.bytes)?;
+        }
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(mut reader: R) -> anyhow::Result<Self> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(anyhow!("Not a stats snapshot..."));
+        }
+
+        let version = reader.read_u32::<LE>()?;
+        if version != SNAPSHOT_VERSION {
+            return Err(anyhow!("Unsupported snapshot version {}", version));
+        }
+
+        let base_addr = reader.read_u64::<LE>()? as usize;
+        let num_regions = reader.read_u32::<LE>()?;
+        let mut regions = Vec::new();
+        for _ in 0..num_regions {
+            let addr = reader.read_u64::<LE>()? as usize;
+            let len = reader.read_u32::<LE>()? as usize;
+            // Grow with the bytes actually there rather than trusting a length read from the file.
+            let mut bytes = Vec::new();
+            reader.by_ref().take(len as u64).read_to_end(&mut bytes)?;
+            if bytes.len() != len {
+                return Err(anyhow!(
+                    "Region at {:#x} is cut short: expected {} bytes, got {}",
+                    addr,
+                    len,
+                    bytes.len()
+                ));
+            }
+            regions.push(Region { addr, bytes });
+        }
+
+        Ok(Self { base_addr, regions })
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        Self::read_from(BufReader::new(File::open(path)?))
+    }
+}
+
+/// Merge overlapping and adjacent regions, so a read recorded in several pieces
+/// replays as one. Reads of the same bytes are expected to agree; where they
+/// don't, the region recorded last wins.
+fn merge_regions(regions: Vec<Region>) -> Vec<Region> {
+    let mut ordered: Vec<(usize, Region)> = regions.into_iter().enumerate().collect();
+    ordered.sort_by_key(|(_, region)| region.addr);
+
+    let mut merged: Vec<(usize, Region)> = Vec::new();
+    for (order, region) in ordered {
+        let Some((last_order, last)) = merged.last_mut() else {
+            merged.push((order, region));
+            continue;
+        };
+        let last_end = last.addr + last.bytes.len();
+        if region.addr > last_end {
+            merged.push((order, region));
+            continue;
+        }
+
+        let offset = region.addr - last.addr;
+        let overlap = (last_end - region.addr).min(region.bytes.len());
+        if order > *last_order {
+            last.bytes[offset..offset + overlap].copy_from_slice(&region.bytes[..overlap]);
+        }
+        last.bytes.extend_from_slice(&region.bytes[overlap..]);
+        *last_order = (*last_order).max(order);
+    }
+    merged.into_iter().map(|(_, region)| region).collect()
+}
+
+impl MemorySource for Snapshot {
+    fn read_n_bytes(&self, addr: usize, num_bytes: usize) -> Result<Cursor<Vec<u8>>, TrackerError> {
+        // Snapshots from before regions were merged can hold a short read of an
+        // address ahead of a longer one, so use whichever has the most.
+        let best = self
+            .regions
+            .iter()
+            .filter(|region| region.addr <= addr && addr < region.addr + region.bytes.len())
+            .max_by_key(|region| region.addr + region.bytes.len());
+
+        match best {
+            Some(region) => {
+                let start = addr - region.addr;
+                let len = num_bytes.min(region.bytes.len() - start);
+                Ok(Cursor::new(region.bytes[start..start + len].to_vec()))
+            }
+            None => Err(TrackerError::ReadFailed {
+                addr,
+                len: num_bytes,
+                field: None,
+            }),
+        }
+    }
+}
+
+/// Passes reads through to `inner` and remembers what came back.
+struct RecordingSource<'a, M: MemorySource + ?Sized> {
+    inner: &'a M,
+    regions: RefCell<Vec<Region>>,
+}
+
+impl<M: MemorySource + ?Sized> MemorySource for RecordingSource<'_, M> {
+    fn read_n_bytes(&self, addr: usize, num_bytes: usize) -> Result<Cursor<Vec<u8>>, TrackerError> {
+        let cursor = self.inner.read_n_bytes(addr, num_bytes)?;
+        self.regions.borrow_mut().push(Region {
+            addr,
+            bytes: cursor.get_ref().clone(),
+        });
+        Ok(cursor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::fake_game;
+
+    #[test]
+    fn short_region_before_long_one() {
+        let snapshot = Snapshot {
+            base_addr: 0,
+            regions: vec![
+                Region {
+                    addr: 0x1000,
+                    bytes: vec![1; 8],
+                },
+                Region {
+                    addr: 0x1000,
+                    bytes: vec![1; 0x2d8],
+                },
+            ],
+        };
+        assert_eq!(
+            snapshot
+                .read_n_bytes(0x1000, 0x2d8)
+                .unwrap()
+                .get_ref()
+                .len(),
+            0x2d8
+        );
+    }
+
+    #[test]
+    fn merges_overlapping_regions() {
+        let regions = vec![
+            Region {
+                addr: 0x1004,
+                bytes: vec![2; 8],
+            },
+            Region {
+                addr: 0x1000,
+                bytes: vec![1; 8],
+            },
+            Region {
+                addr: 0x100c,
+                bytes: vec![3; 4],
+            },
+            Region {
+                addr: 0x2000,
+                bytes: vec![4; 4],
+            },
+        ];
+        let merged = merge_regions(regions);
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged[0].addr, 0x1000);
+        assert_eq!(
+            merged[0].bytes,
+            [1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3]
+        );
+        assert_eq!(merged[1].addr, 0x2000);
+    }
+
+    #[test]
+    fn capture_round_trip() {
+        let mut stats = Stats {
+            crates_opened: 12.0,
+            ..Default::default()
+        };
+        stats.enemy_deaths.spider = 3.0;
+        stats.enemy_kills.monkey = 4.0;
+        let (memory, base_addr) = fake_game(&stats);
+        let layout = StatsLayout::builtin();
+
+        let snapshot = Snapshot::capture(&memory, base_addr, layout).unwrap();
+        let mut file = Vec::new();
+        snapshot.write_to(&mut file).unwrap();
+        let replayed = Snapshot::read_from(file.as_slice()).unwrap();
+
+        assert_eq!(replayed, snapshot);
+        assert_eq!(Stats::read(&replayed, base_addr, layout).unwrap(), stats);
+    }
+
+    #[test]
+    fn rejects_truncated_regions() {
+        let snapshot = Snapshot {
+            base_addr: 0x400000,
+            regions: vec![Region {
+                addr: 0x1000,
+                bytes: vec![1; 16],
+            }],
+        };
+        let mut file = Vec::new();
+        snapshot.write_to(&mut file).unwrap();
+        assert!(Snapshot::read_from(&file[..file.len() - 1]).is_err());
+
+        // A corrupt length mustn't be allocated up front.
+        let len_at = file.len() - 16 - 4;
+        file[len_at..len_at + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(Snapshot::read_from(file.as_slice()).is_err());
+    }
+
+    #[test]
+    fn short_read_recorded_before_the_stat_block() {
+        let stats = Stats {
+            dice_games_lost: 2.0,
+            ..Default::default()
+        };
+        let (memory, base_addr) = fake_game(&stats);
+        let layout = StatsLayout::builtin();
+
+        // Like a GameMaker slot read while resolving globals, ahead of the block.
+        let recorder = RecordingSource {
+            inner: &memory,
+            regions: RefCell::new(Vec::new()),
+        };
+        let block_addr = layout.block_addr(&recorder, base_addr).unwrap();
+        recorder.read_n_bytes(block_addr, 8).unwrap();
+        Stats::read(&recorder, base_addr, layout).unwrap();
+        let regions = recorder.regions.into_inner();
+
+        for regions in [regions.clone(), merge_regions(regions)] {
+            let snapshot = Snapshot { base_addr, regions };
+            assert_eq!(Stats::read(&snapshot, base_addr, layout).unwrap(), stats);
+        }
+    }
+}
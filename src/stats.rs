@@ -1,13 +1,14 @@
-use std::io::Seek;
-use std::io::SeekFrom::Current;
+use serde::{Deserialize, Serialize};
 
-use byteorder::{ReadBytesExt, LE};
-use serde::Serialize;
-use winapi::um::winnt::HANDLE;
+use crate::error::TrackerError;
+use crate::layout::StatsLayout;
+use crate::mem_reader::{GameProcess, MemorySource};
+use crate::schema::SCHEMA_VERSION;
 
-use crate::mem_reader::{read_n_bytes, read_uptr32_t};
-
-#[derive(Clone, Default, Debug, Serialize, PartialEq)]
+// Counters missing from serialized stats are zero, so older data still loads
+// after counters are added.
+#[derive(Clone, Default, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
 pub struct LevelDeaths {
     pub level_1: f64,
     pub level_2: f64,
@@ -27,7 +28,8 @@ pub struct LevelDeaths {
     pub level_16: f64,
 }
 
-#[derive(Clone, Default, Debug, Serialize, PartialEq)]
+#[derive(Clone, Default, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
 pub struct EnemyDeaths {
     pub bat: f64,
     pub snake: f64,
@@ -55,7 +57,8 @@ pub struct EnemyDeaths {
     pub ghost: f64,
 }
 
-#[derive(Clone, Default, Debug, Serialize, PartialEq)]
+#[derive(Clone, Default, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
 pub struct MiscDeaths {
     pub rock: f64,
     pub explosion: f64,
@@ -71,7 +74,8 @@ pub struct MiscDeaths {
     pub lava: f64,
 }
 
-#[derive(Clone, Default, Debug, Serialize, PartialEq)]
+#[derive(Clone, Default, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
 pub struct EnemyKills {
     pub bat: f64,
     pub snake: f64,
@@ -98,8 +102,14 @@ pub struct EnemyKills {
     pub olmec: f64,
 }
 
-#[derive(Clone, Default, Debug, Serialize, PartialEq)]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
 pub struct Stats {
+    /// The `SCHEMA_VERSION` these stats were serialized with. Zero for stats saved
+    /// before versioning; see `Stats::from_json`.
+    #[serde(default)]
+    pub schema_version: u32,
+
     pub crates_opened: f64,
     pub chests_opened: f64,
     pub idols_grabbed: f64,
@@ -121,475 +131,645 @@ pub struct Stats {
     pub enemy_kills: EnemyKills,
 }
 
-impl Stats {
-    pub fn pprint(&self) {
-        println!("Crates Opened:     {}", self.crates_opened);
-        println!("Chests Opened:     {}", self.chests_opened);
-
-        println!("Idols Grabbed:     {}", self.idols_grabbed);
-        println!("Idols Converted:   {}", self.idols_converted);
-
-        println!("Damsels Grabbed:   {}", self.damsels_grabbed);
-        println!("Kisses Bought:     {}", self.kisses_bought);
-        println!("Damsels Bought:    {}", self.damsels_bought);
-        println!("Damsels Saved:     {}", self.damsels_saved);
-        println!("Damsels Killed:    {}", self.damsels_killed);
-
-        println!("Items Bought:      {}", self.items_bought);
-        println!("Items Stolen:      {}", self.items_stolen);
+impl Default for Stats {
+    fn default() -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            crates_opened: 0.0,
+            chests_opened: 0.0,
+            idols_grabbed: 0.0,
+            idols_converted: 0.0,
+            damsels_grabbed: 0.0,
+            kisses_bought: 0.0,
+            damsels_bought: 0.0,
+            damsels_saved: 0.0,
+            damsels_killed: 0.0,
+            items_bought: 0.0,
+            items_stolen: 0.0,
+            dice_games_played: 0.0,
+            dice_games_won: 0.0,
+            dice_games_lost: 0.0,
+            level_deaths: LevelDeaths::default(),
+            enemy_deaths: EnemyDeaths::default(),
+            misc_deaths: MiscDeaths::default(),
+            enemy_kills: EnemyKills::default(),
+        }
+    }
+}
 
-        println!("Dice Games Played: {}", self.dice_games_played);
-        println!("Dice Games Won:    {}", self.dice_games_won);
-        println!("Dice Games Lost:   {}", self.dice_games_lost);
+/// A counter that changed between two reads.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct StatChange {
+    /// Dotted key of the counter, e.g. `"enemy_kills.bat"`.
+    pub key: &'static str,
+    pub old: f64,
+    pub new: f64,
+}
 
-        println!("");
-        println!("Levels:");
-        println!(
-            "  Mines:     {:5} {:5} {:5} {:5}",
-            self.level_deaths.level_1,
-            self.level_deaths.level_2,
-            self.level_deaths.level_3,
-            self.level_deaths.level_4
-        );
-        println!(
-            "  Jungle:    {:5} {:5} {:5} {:5}",
-            self.level_deaths.level_5,
-            self.level_deaths.level_6,
-            self.level_deaths.level_7,
-            self.level_deaths.level_8
-        );
-        println!(
-            "  Ice Caves: {:5} {:5} {:5} {:5}",
-            self.level_deaths.level_9,
-            self.level_deaths.level_10,
-            self.level_deaths.level_11,
-            self.level_deaths.level_12
-        );
-        println!(
-            "  Temple:    {:5} {:5} {:5} {:5}",
-            self.level_deaths.level_13,
-            self.level_deaths.level_14,
-            self.level_deaths.level_15,
-            self.level_deaths.level_16
-        );
+/// Every counter that differs between two `Stats`, in `Stats::KEYS` order.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct StatsDelta {
+    pub changes: Vec<StatChange>,
+}
 
-        println!("");
-        println!("Misc:");
-        println!("  Rock:         {}", self.misc_deaths.rock);
-        println!("  Explosion:    {}", self.misc_deaths.explosion);
-        println!("  Crushed:      {}", self.misc_deaths.crushed);
-        println!("  Long Fall:    {}", self.misc_deaths.long_fall);
-        println!("  Spikes:       {}", self.misc_deaths.spikes);
-        println!("  Boulder:      {}", self.misc_deaths.boulder);
-        println!("  Arrow Trap:   {}", self.misc_deaths.arrow_trap);
-        println!("  Spear Trap:   {}", self.misc_deaths.spear_trap);
-        println!("  Smash Trap:   {}", self.misc_deaths.smash_trap);
-        println!("  Ceiling Trap: {}", self.misc_deaths.ceiling_trap);
-        println!("  Pit:          {}", self.misc_deaths.pit);
-        println!("  Lava:         {}", self.misc_deaths.lava);
-
-        println!("");
-        println!("Enemies:       Deaths   Kills");
-        println!(
-            "  Bat:          {:5}   {}",
-            self.enemy_deaths.bat, self.enemy_kills.bat
-        );
-        println!(
-            "  Snake:        {:5}   {}",
-            self.enemy_deaths.snake, self.enemy_kills.snake
-        );
-        println!(
-            "  Spider:       {:5}   {}",
-            self.enemy_deaths.spider, self.enemy_kills.spider
-        );
-        println!(
-            "  Giant Spider: {:5}   {}",
-            self.enemy_deaths.giant_spider, self.enemy_kills.giant_spider
-        );
-        println!(
-            "  Caveman:      {:5}   {}",
-            self.enemy_deaths.caveman, self.enemy_kills.caveman
-        );
-        println!(
-            "  Skeleton:     {:5}   {}",
-            self.enemy_deaths.skeleton, self.enemy_kills.skeleton
-        );
-        println!(
-            "  Zombie:       {:5}   {}",
-            self.enemy_deaths.zombie, self.enemy_kills.zombie
-        );
-        println!(
-            "  Vampire:      {:5}   {}",
-            self.enemy_deaths.vampire, self.enemy_kills.vampire
-        );
-        println!(
-            "  Frog:         {:5}   {}",
-            self.enemy_deaths.frog, self.enemy_kills.frog
-        );
-        println!("  Monkey:       {:5}   {}", "", self.enemy_kills.monkey);
-        println!(
-            "  Fire_frog:    {:5}   {}",
-            self.enemy_deaths.fire_frog, self.enemy_kills.fire_frog
-        );
-        println!(
-            "  Mantrap:      {:5}   {}",
-            self.enemy_deaths.mantrap, self.enemy_kills.mantrap
-        );
-        println!(
-            "  Piranha:      {:5}   {}",
-            self.enemy_deaths.piranha, self.enemy_kills.piranha
-        );
-        println!(
-            "  Megamouth:    {:5}   {}",
-            self.enemy_deaths.megamouth, self.enemy_kills.megamouth
-        );
-        println!(
-            "  Yeti:         {:5}   {}",
-            self.enemy_deaths.yeti, self.enemy_kills.yeti
-        );
-        println!(
-            "  Yeti King:    {:5}   {}",
-            self.enemy_deaths.yeti_king, self.enemy_kills.yeti_king
-        );
-        println!(
-            "  Alien:        {:5}   {}",
-            self.enemy_deaths.alien, self.enemy_kills.alien
-        );
-        println!(
-            "  Ufo:          {:5}   {}",
-            self.enemy_deaths.ufo, self.enemy_kills.ufo
-        );
-        println!(
-            "  Alien_boss:   {:5}   {}",
-            self.enemy_deaths.alien_boss, self.enemy_kills.alien_boss
-        );
-        println!(
-            "  Hawkman:      {:5}   {}",
-            self.enemy_deaths.hawkman, self.enemy_kills.hawkman
-        );
-        println!(
-            "  Shopkeeper:   {:5}   {}",
-            self.enemy_deaths.shopkeeper, self.enemy_kills.shopkeeper
-        );
-        println!(
-            "  Tomb Lord:    {:5}   {}",
-            self.enemy_deaths.tomb_lord, self.enemy_kills.tomb_lord
-        );
-        println!("  Magma Man:    {:5}", self.enemy_deaths.magma_man);
-        println!(
-            "  Olmec:        {:5}   {}",
-            self.enemy_deaths.olmec, self.enemy_kills.olmec
-        );
-        println!("  Ghost:        {:5}", self.enemy_deaths.ghost);
+impl StatsDelta {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
     }
 
-    pub fn from_memory(process: HANDLE, base_addr: usize) -> anyhow::Result<Self> {
-        let mut stats = Self::default();
-        stats.update_from_memory(process, base_addr)?;
-        Ok(stats)
+    /// The change to `key`, if it changed.
+    pub fn get(&self, key: &str) -> Option<&StatChange> {
+        self.changes.iter().find(|change| change.key == key)
     }
+}
 
-    pub fn update_from_memory(&mut self, process: HANDLE, base_addr: usize) -> anyhow::Result<()> {
-        let start = base_addr + 0x0018f124;
-        let offset = read_uptr32_t(process, start)? as usize;
-        let offset = read_uptr32_t(process, offset + 0x4)? as usize;
-        let offset = offset + 0xd58;
-
-        let mut cursor = read_n_bytes(process, offset, 0x2d8)?;
-
-        self.crates_opened = cursor.read_f64::<LE>()?;
-        cursor.seek(Current(8 * 4))?;
-
-        self.chests_opened = cursor.read_f64::<LE>()?;
-        cursor.seek(Current(8 * 4))?;
-
-        self.idols_grabbed = cursor.read_f64::<LE>()?;
-        cursor.seek(Current(8 * 4))?;
-
-        self.idols_converted = cursor.read_f64::<LE>()?;
-        cursor.seek(Current(8 * 4))?;
-
-        self.damsels_grabbed = cursor.read_f64::<LE>()?;
-        cursor.seek(Current(8 * 4))?;
-
-        self.kisses_bought = cursor.read_f64::<LE>()?;
-        cursor.seek(Current(8 * 4))?;
-
-        self.damsels_bought = cursor.read_f64::<LE>()?;
-        cursor.seek(Current(8 * 4))?;
-
-        self.damsels_saved = cursor.read_f64::<LE>()?;
-        cursor.seek(Current(8 * 4))?;
-
-        self.damsels_killed = cursor.read_f64::<LE>()?;
-        cursor.seek(Current(8 * 4))?;
-
-        self.items_bought = cursor.read_f64::<LE>()?;
-        cursor.seek(Current(8 * 4))?;
-
-        self.items_stolen = cursor.read_f64::<LE>()?;
-        cursor.seek(Current(8 * 4))?;
-
-        self.dice_games_played = cursor.read_f64::<LE>()?;
-        cursor.seek(Current(8 * 4))?;
-
-        self.dice_games_won = cursor.read_f64::<LE>()?;
-        cursor.seek(Current(8 * 4))?;
-
-        self.dice_games_lost = cursor.read_f64::<LE>()?;
-        cursor.seek(Current(8 * 4))?;
-
-        // Level Deaths
-        self.level_deaths.level_1 = cursor.read_f64::<LE>()?;
-
-        let level_deaths_list_offset =
-            read_uptr32_t(process, (offset as u64 + cursor.position() + 0x8) as usize)? as usize;
-        let level_deaths_list_offset = read_uptr32_t(process, level_deaths_list_offset)? as usize;
-        let mut level_deaths_cursor =
-            read_n_bytes(process, level_deaths_list_offset + 0x20, 0x158)?;
-
-        self.level_deaths.level_2 = level_deaths_cursor.read_f64::<LE>()?;
-        level_deaths_cursor.seek(Current(0x10))?;
-
-        self.level_deaths.level_3 = level_deaths_cursor.read_f64::<LE>()?;
-        level_deaths_cursor.seek(Current(0x10))?;
-
-        self.level_deaths.level_4 = level_deaths_cursor.read_f64::<LE>()?;
-        level_deaths_cursor.seek(Current(0x10))?;
-
-        self.level_deaths.level_5 = level_deaths_cursor.read_f64::<LE>()?;
-        level_deaths_cursor.seek(Current(0x10))?;
-
-        self.level_deaths.level_6 = level_deaths_cursor.read_f64::<LE>()?;
-        level_deaths_cursor.seek(Current(0x10))?;
-
-        self.level_deaths.level_7 = level_deaths_cursor.read_f64::<LE>()?;
-        level_deaths_cursor.seek(Current(0x10))?;
-
-        self.level_deaths.level_8 = level_deaths_cursor.read_f64::<LE>()?;
-        level_deaths_cursor.seek(Current(0x10))?;
-
-        self.level_deaths.level_9 = level_deaths_cursor.read_f64::<LE>()?;
-        level_deaths_cursor.seek(Current(0x10))?;
-
-        self.level_deaths.level_10 = level_deaths_cursor.read_f64::<LE>()?;
-        level_deaths_cursor.seek(Current(0x10))?;
-
-        self.level_deaths.level_11 = level_deaths_cursor.read_f64::<LE>()?;
-        level_deaths_cursor.seek(Current(0x10))?;
-
-        self.level_deaths.level_12 = level_deaths_cursor.read_f64::<LE>()?;
-        level_deaths_cursor.seek(Current(0x10))?;
-
-        self.level_deaths.level_13 = level_deaths_cursor.read_f64::<LE>()?;
-        level_deaths_cursor.seek(Current(0x10))?;
-
-        self.level_deaths.level_14 = level_deaths_cursor.read_f64::<LE>()?;
-        level_deaths_cursor.seek(Current(0x10))?;
-
-        self.level_deaths.level_15 = level_deaths_cursor.read_f64::<LE>()?;
-        level_deaths_cursor.seek(Current(0x10))?;
-
-        self.level_deaths.level_16 = level_deaths_cursor.read_f64::<LE>()?;
-        level_deaths_cursor.seek(Current(0x10))?;
-
-        cursor.seek(Current(8 * 4))?;
-
-        // Enemy Deaths
-        self.enemy_deaths.bat = cursor.read_f64::<LE>()?;
-        let enemy_deaths_list_offset =
-            read_uptr32_t(process, (offset as u64 + cursor.position() + 0x8) as usize)? as usize;
-        let enemy_deaths_list_offset = read_uptr32_t(process, enemy_deaths_list_offset)? as usize;
-        let mut enemy_deaths_cursor =
-            read_n_bytes(process, enemy_deaths_list_offset + 0x20, 0x218)?;
-
-        self.enemy_deaths.snake = enemy_deaths_cursor.read_f64::<LE>()?;
-        enemy_deaths_cursor.seek(Current(0x10))?;
-
-        self.enemy_deaths.spider = enemy_deaths_cursor.read_f64::<LE>()?;
-        enemy_deaths_cursor.seek(Current(0x10))?;
-
-        self.enemy_deaths.giant_spider = enemy_deaths_cursor.read_f64::<LE>()?;
-        enemy_deaths_cursor.seek(Current(0x10))?;
-
-        self.enemy_deaths.caveman = enemy_deaths_cursor.read_f64::<LE>()?;
-        enemy_deaths_cursor.seek(Current(0x10))?;
-
-        self.enemy_deaths.skeleton = enemy_deaths_cursor.read_f64::<LE>()?;
-        enemy_deaths_cursor.seek(Current(0x10))?;
-
-        self.enemy_deaths.zombie = enemy_deaths_cursor.read_f64::<LE>()?;
-        enemy_deaths_cursor.seek(Current(0x10))?;
-
-        self.enemy_deaths.vampire = enemy_deaths_cursor.read_f64::<LE>()?;
-        enemy_deaths_cursor.seek(Current(0x10))?;
-
-        self.enemy_deaths.frog = enemy_deaths_cursor.read_f64::<LE>()?;
-        enemy_deaths_cursor.seek(Current(0x10))?;
-
-        self.enemy_deaths.fire_frog = enemy_deaths_cursor.read_f64::<LE>()?;
-        enemy_deaths_cursor.seek(Current(0x10))?;
-
-        self.enemy_deaths.mantrap = enemy_deaths_cursor.read_f64::<LE>()?;
-        enemy_deaths_cursor.seek(Current(0x10))?;
-
-        self.enemy_deaths.piranha = enemy_deaths_cursor.read_f64::<LE>()?;
-        enemy_deaths_cursor.seek(Current(0x10))?;
-
-        self.enemy_deaths.megamouth = enemy_deaths_cursor.read_f64::<LE>()?;
-        enemy_deaths_cursor.seek(Current(0x10))?;
-
-        self.enemy_deaths.yeti = enemy_deaths_cursor.read_f64::<LE>()?;
-        enemy_deaths_cursor.seek(Current(0x10))?;
-
-        self.enemy_deaths.yeti_king = enemy_deaths_cursor.read_f64::<LE>()?;
-        enemy_deaths_cursor.seek(Current(0x10))?;
-
-        self.enemy_deaths.alien = enemy_deaths_cursor.read_f64::<LE>()?;
-        enemy_deaths_cursor.seek(Current(0x10))?;
-
-        self.enemy_deaths.ufo = enemy_deaths_cursor.read_f64::<LE>()?;
-        enemy_deaths_cursor.seek(Current(0x10))?;
-
-        self.enemy_deaths.alien_boss = enemy_deaths_cursor.read_f64::<LE>()?;
-        enemy_deaths_cursor.seek(Current(0x10))?;
-
-        self.enemy_deaths.hawkman = enemy_deaths_cursor.read_f64::<LE>()?;
-        enemy_deaths_cursor.seek(Current(0x10))?;
-
-        self.enemy_deaths.shopkeeper = enemy_deaths_cursor.read_f64::<LE>()?;
-        enemy_deaths_cursor.seek(Current(0x10))?;
-
-        self.enemy_deaths.tomb_lord = enemy_deaths_cursor.read_f64::<LE>()?;
-        enemy_deaths_cursor.seek(Current(0x10))?;
-
-        self.enemy_deaths.magma_man = enemy_deaths_cursor.read_f64::<LE>()?;
-        enemy_deaths_cursor.seek(Current(0x10))?;
-
-        self.enemy_deaths.olmec = enemy_deaths_cursor.read_f64::<LE>()?;
-        enemy_deaths_cursor.seek(Current(0x10))?;
-
-        self.enemy_deaths.ghost = enemy_deaths_cursor.read_f64::<LE>()?;
-        enemy_deaths_cursor.seek(Current(0x10))?;
-
-        cursor.seek(Current(8 * 4))?;
-
-        // Misc Deaths
-        self.misc_deaths.rock = cursor.read_f64::<LE>()?;
-        let misc_deaths_list_offset =
-            read_uptr32_t(process, (offset as u64 + cursor.position() + 0x8) as usize)? as usize;
-        let misc_deaths_list_offset = read_uptr32_t(process, misc_deaths_list_offset)? as usize;
-        let mut misc_deaths_cursor = read_n_bytes(process, misc_deaths_list_offset + 0x20, 0xf8)?;
-
-        self.misc_deaths.explosion = misc_deaths_cursor.read_f64::<LE>()?;
-        misc_deaths_cursor.seek(Current(0x10))?;
-
-        self.misc_deaths.crushed = misc_deaths_cursor.read_f64::<LE>()?;
-        misc_deaths_cursor.seek(Current(0x10))?;
-
-        self.misc_deaths.long_fall = misc_deaths_cursor.read_f64::<LE>()?;
-        misc_deaths_cursor.seek(Current(0x10))?;
+/// The group a counter belongs to, named after its field on `Stats`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Category {
+    /// Counters directly on `Stats`, like crates opened.
+    General,
+    LevelDeaths,
+    EnemyDeaths,
+    MiscDeaths,
+    EnemyKills,
+}
 
-        self.misc_deaths.spikes = misc_deaths_cursor.read_f64::<LE>()?;
-        misc_deaths_cursor.seek(Current(0x10))?;
+/// Which counters the game keeps for the thing a counter counts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Tally {
+    Kill,
+    Death,
+    /// Enemies that can both kill the player and be killed.
+    Both,
+}
 
-        self.misc_deaths.boulder = misc_deaths_cursor.read_f64::<LE>()?;
-        misc_deaths_cursor.seek(Current(0x10))?;
+/// A counter in `Stats`, with what the frontend needs to show it.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct StatKey {
+    /// Dotted key, as used by `Stats::get`.
+    pub key: &'static str,
+    pub category: Category,
+    /// Human readable name, e.g. `"Giant Spider"` or `"2-3"` for level deaths.
+    pub name: &'static str,
+    /// File name of the icon in `images/stats-icons`, without the extension.
+    pub icon: &'static str,
+    /// `None` for counters that are neither kills nor deaths.
+    pub tally: Option<Tally>,
+}
 
-        self.misc_deaths.arrow_trap = misc_deaths_cursor.read_f64::<LE>()?;
-        misc_deaths_cursor.seek(Current(0x10))?;
+impl StatKey {
+    /// Look up a counter by its dotted key.
+    pub fn find(key: &str) -> Option<&'static StatKey> {
+        Self::ALL.iter().find(|stat| stat.key == key)
+    }
+}
 
-        self.misc_deaths.spear_trap = misc_deaths_cursor.read_f64::<LE>()?;
-        misc_deaths_cursor.seek(Current(0x10))?;
+/// Largest counter value `Stats::validate` accepts.
+pub const MAX_PLAUSIBLE: f64 = 1_000_000_000.0;
 
-        self.misc_deaths.smash_trap = misc_deaths_cursor.read_f64::<LE>()?;
-        misc_deaths_cursor.seek(Current(0x10))?;
+macro_rules! tally {
+    (None) => {
+        None
+    };
+    ($tally:ident) => {
+        Some(Tally::$tally)
+    };
+}
 
-        self.misc_deaths.ceiling_trap = misc_deaths_cursor.read_f64::<LE>()?;
-        misc_deaths_cursor.seek(Current(0x10))?;
+/// Generates `StatKey::ALL` and the key-based accessors on `Stats` from a list of
+/// `"key" => field.path: Category, "Name", "icon", Tally` rows.
+macro_rules! stat_fields {
+    ($($key:literal => $($field:ident).+: $category:ident, $name:literal, $icon:literal, $tally:ident,)*) => {
+        impl StatKey {
+            /// Every counter, in `Stats::KEYS` order.
+            pub const ALL: &'static [StatKey] = &[$(StatKey {
+                key: $key,
+                category: Category::$category,
+                name: $name,
+                icon: $icon,
+                tally: tally!($tally),
+            }),*];
+        }
+
+        impl Stats {
+            /// Dotted keys of every counter, e.g. `"enemy_kills.bat"`.
+            pub const KEYS: &'static [&'static str] = &[$($key),*];
+
+            /// Look up a counter by its dotted key.
+            pub fn get(&self, key: &str) -> Option<f64> {
+                match key {
+                    $($key => Some(self.$($field).+),)*
+                    _ => None,
+                }
+            }
+
+            pub fn field_mut(&mut self, key: &str) -> Option<&mut f64> {
+                match key {
+                    $($key => Some(&mut self.$($field).+),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
 
-        self.misc_deaths.pit = misc_deaths_cursor.read_f64::<LE>()?;
-        misc_deaths_cursor.seek(Current(0x10))?;
+stat_fields! {
+    "crates_opened" => crates_opened: General, "Crates Opened", "crates_opened", None,
+    "chests_opened" => chests_opened: General, "Chests Opened", "chests_opened", None,
+    "idols_grabbed" => idols_grabbed: General, "Idols Grabbed", "idols_grabbed", None,
+    "idols_converted" => idols_converted: General, "Idols Converted", "idols_converted", None,
+    "damsels_grabbed" => damsels_grabbed: General, "Damsels Grabbed", "damsels_grabbed", None,
+    "kisses_bought" => kisses_bought: General, "Kisses Bought", "kisses_bought", None,
+    "damsels_bought" => damsels_bought: General, "Damsels Bought", "damsels_bought", None,
+    "damsels_saved" => damsels_saved: General, "Damsels Saved", "damsels_saved", None,
+    "damsels_killed" => damsels_killed: General, "Damsels Killed", "damsels_killed", None,
+    "items_bought" => items_bought: General, "Items Bought", "items_bought", None,
+    "items_stolen" => items_stolen: General, "Items Stolen", "items_stolen", None,
+    "dice_games_played" => dice_games_played: General, "Dice Games Played", "dice_games_played", None,
+    "dice_games_won" => dice_games_won: General, "Dice Games Won", "dice_games_won", None,
+    "dice_games_lost" => dice_games_lost: General, "Dice Games Lost", "dice_games_lost", None,
+    "level_deaths.level_1" => level_deaths.level_1: LevelDeaths, "1-1", "level_deaths_level_1", Death,
+    "level_deaths.level_2" => level_deaths.level_2: LevelDeaths, "1-2", "level_deaths_level_2", Death,
+    "level_deaths.level_3" => level_deaths.level_3: LevelDeaths, "1-3", "level_deaths_level_3", Death,
+    "level_deaths.level_4" => level_deaths.level_4: LevelDeaths, "1-4", "level_deaths_level_4", Death,
+    "level_deaths.level_5" => level_deaths.level_5: LevelDeaths, "2-1", "level_deaths_level_5", Death,
+    "level_deaths.level_6" => level_deaths.level_6: LevelDeaths, "2-2", "level_deaths_level_6", Death,
+    "level_deaths.level_7" => level_deaths.level_7: LevelDeaths, "2-3", "level_deaths_level_7", Death,
+    "level_deaths.level_8" => level_deaths.level_8: LevelDeaths, "2-4", "level_deaths_level_8", Death,
+    "level_deaths.level_9" => level_deaths.level_9: LevelDeaths, "3-1", "level_deaths_level_9", Death,
+    "level_deaths.level_10" => level_deaths.level_10: LevelDeaths, "3-2", "level_deaths_level_10", Death,
+    "level_deaths.level_11" => level_deaths.level_11: LevelDeaths, "3-3", "level_deaths_level_11", Death,
+    "level_deaths.level_12" => level_deaths.level_12: LevelDeaths, "3-4", "level_deaths_level_12", Death,
+    "level_deaths.level_13" => level_deaths.level_13: LevelDeaths, "4-1", "level_deaths_level_13", Death,
+    "level_deaths.level_14" => level_deaths.level_14: LevelDeaths, "4-2", "level_deaths_level_14", Death,
+    "level_deaths.level_15" => level_deaths.level_15: LevelDeaths, "4-3", "level_deaths_level_15", Death,
+    "level_deaths.level_16" => level_deaths.level_16: LevelDeaths, "4-4", "level_deaths_level_16", Death,
+    "enemy_deaths.bat" => enemy_deaths.bat: EnemyDeaths, "Bat", "enemy_bat", Both,
+    "enemy_deaths.snake" => enemy_deaths.snake: EnemyDeaths, "Snake", "enemy_snake", Both,
+    "enemy_deaths.spider" => enemy_deaths.spider: EnemyDeaths, "Spider", "enemy_spider", Both,
+    "enemy_deaths.giant_spider" => enemy_deaths.giant_spider: EnemyDeaths, "Giant Spider", "enemy_giant_spider", Both,
+    "enemy_deaths.caveman" => enemy_deaths.caveman: EnemyDeaths, "Caveman", "enemy_caveman", Both,
+    "enemy_deaths.skeleton" => enemy_deaths.skeleton: EnemyDeaths, "Skeleton", "enemy_skeleton", Both,
+    "enemy_deaths.zombie" => enemy_deaths.zombie: EnemyDeaths, "Zombie", "enemy_zombie", Both,
+    "enemy_deaths.vampire" => enemy_deaths.vampire: EnemyDeaths, "Vampire", "enemy_vampire", Both,
+    "enemy_deaths.frog" => enemy_deaths.frog: EnemyDeaths, "Frog", "enemy_frog", Both,
+    "enemy_deaths.fire_frog" => enemy_deaths.fire_frog: EnemyDeaths, "Fire Frog", "enemy_fire_frog", Both,
+    "enemy_deaths.mantrap" => enemy_deaths.mantrap: EnemyDeaths, "Mantrap", "enemy_mantrap", Both,
+    "enemy_deaths.piranha" => enemy_deaths.piranha: EnemyDeaths, "Piranha", "enemy_piranha", Both,
+    "enemy_deaths.megamouth" => enemy_deaths.megamouth: EnemyDeaths, "Megamouth", "enemy_megamouth", Both,
+    "enemy_deaths.yeti" => enemy_deaths.yeti: EnemyDeaths, "Yeti", "enemy_yeti", Both,
+    "enemy_deaths.yeti_king" => enemy_deaths.yeti_king: EnemyDeaths, "Yeti King", "enemy_yeti_king", Both,
+    "enemy_deaths.alien" => enemy_deaths.alien: EnemyDeaths, "Alien", "enemy_alien", Both,
+    "enemy_deaths.ufo" => enemy_deaths.ufo: EnemyDeaths, "UFO", "enemy_ufo", Both,
+    "enemy_deaths.alien_boss" => enemy_deaths.alien_boss: EnemyDeaths, "Alien Boss", "enemy_alien_boss", Both,
+    "enemy_deaths.hawkman" => enemy_deaths.hawkman: EnemyDeaths, "Hawkman", "enemy_hawkman", Both,
+    "enemy_deaths.shopkeeper" => enemy_deaths.shopkeeper: EnemyDeaths, "Shopkeeper", "enemy_shopkeeper", Both,
+    "enemy_deaths.tomb_lord" => enemy_deaths.tomb_lord: EnemyDeaths, "Tomb Lord", "enemy_tomb_lord", Both,
+    "enemy_deaths.magma_man" => enemy_deaths.magma_man: EnemyDeaths, "Magma Man", "enemy_magma_man", Death,
+    "enemy_deaths.olmec" => enemy_deaths.olmec: EnemyDeaths, "Olmec", "enemy_olmec", Both,
+    "enemy_deaths.ghost" => enemy_deaths.ghost: EnemyDeaths, "Ghost", "enemy_ghost", Death,
+    "misc_deaths.rock" => misc_deaths.rock: MiscDeaths, "Rock", "misc_deaths_rock", Death,
+    "misc_deaths.explosion" => misc_deaths.explosion: MiscDeaths, "Explosion", "misc_deaths_explosion", Death,
+    "misc_deaths.crushed" => misc_deaths.crushed: MiscDeaths, "Crushed", "misc_deaths_crushed", Death,
+    "misc_deaths.long_fall" => misc_deaths.long_fall: MiscDeaths, "Long Fall", "misc_deaths_long_fall", Death,
+    "misc_deaths.spikes" => misc_deaths.spikes: MiscDeaths, "Spikes", "misc_deaths_spikes", Death,
+    "misc_deaths.boulder" => misc_deaths.boulder: MiscDeaths, "Boulder", "misc_deaths_boulder", Death,
+    "misc_deaths.arrow_trap" => misc_deaths.arrow_trap: MiscDeaths, "Arrow Trap", "misc_deaths_arrow_trap", Death,
+    "misc_deaths.spear_trap" => misc_deaths.spear_trap: MiscDeaths, "Spear Trap", "misc_deaths_spear_trap", Death,
+    "misc_deaths.smash_trap" => misc_deaths.smash_trap: MiscDeaths, "Smash Trap", "misc_deaths_smash_trap", Death,
+    "misc_deaths.ceiling_trap" => misc_deaths.ceiling_trap: MiscDeaths, "Ceiling Trap", "misc_deaths_ceiling_trap", Death,
+    "misc_deaths.pit" => misc_deaths.pit: MiscDeaths, "Pit", "misc_deaths_pit", Death,
+    "misc_deaths.lava" => misc_deaths.lava: MiscDeaths, "Lava", "misc_deaths_lava", Death,
+    "enemy_kills.bat" => enemy_kills.bat: EnemyKills, "Bat", "enemy_bat", Both,
+    "enemy_kills.snake" => enemy_kills.snake: EnemyKills, "Snake", "enemy_snake", Both,
+    "enemy_kills.spider" => enemy_kills.spider: EnemyKills, "Spider", "enemy_spider", Both,
+    "enemy_kills.giant_spider" => enemy_kills.giant_spider: EnemyKills, "Giant Spider", "enemy_giant_spider", Both,
+    "enemy_kills.caveman" => enemy_kills.caveman: EnemyKills, "Caveman", "enemy_caveman", Both,
+    "enemy_kills.skeleton" => enemy_kills.skeleton: EnemyKills, "Skeleton", "enemy_skeleton", Both,
+    "enemy_kills.zombie" => enemy_kills.zombie: EnemyKills, "Zombie", "enemy_zombie", Both,
+    "enemy_kills.vampire" => enemy_kills.vampire: EnemyKills, "Vampire", "enemy_vampire", Both,
+    "enemy_kills.frog" => enemy_kills.frog: EnemyKills, "Frog", "enemy_frog", Both,
+    "enemy_kills.fire_frog" => enemy_kills.fire_frog: EnemyKills, "Fire Frog", "enemy_fire_frog", Both,
+    "enemy_kills.mantrap" => enemy_kills.mantrap: EnemyKills, "Mantrap", "enemy_mantrap", Both,
+    "enemy_kills.piranha" => enemy_kills.piranha: EnemyKills, "Piranha", "enemy_piranha", Both,
+    "enemy_kills.megamouth" => enemy_kills.megamouth: EnemyKills, "Megamouth", "enemy_megamouth", Both,
+    "enemy_kills.yeti" => enemy_kills.yeti: EnemyKills, "Yeti", "enemy_yeti", Both,
+    "enemy_kills.yeti_king" => enemy_kills.yeti_king: EnemyKills, "Yeti King", "enemy_yeti_king", Both,
+    "enemy_kills.alien" => enemy_kills.alien: EnemyKills, "Alien", "enemy_alien", Both,
+    "enemy_kills.ufo" => enemy_kills.ufo: EnemyKills, "UFO", "enemy_ufo", Both,
+    "enemy_kills.alien_boss" => enemy_kills.alien_boss: EnemyKills, "Alien Boss", "enemy_alien_boss", Both,
+    "enemy_kills.hawkman" => enemy_kills.hawkman: EnemyKills, "Hawkman", "enemy_hawkman", Both,
+    "enemy_kills.shopkeeper" => enemy_kills.shopkeeper: EnemyKills, "Shopkeeper", "enemy_shopkeeper", Both,
+    "enemy_kills.tomb_lord" => enemy_kills.tomb_lord: EnemyKills, "Tomb Lord", "enemy_tomb_lord", Both,
+    "enemy_kills.olmec" => enemy_kills.olmec: EnemyKills, "Olmec", "enemy_olmec", Both,
+    "enemy_kills.monkey" => enemy_kills.monkey: EnemyKills, "Monkey", "enemy_monkey", Kill,
+}
 
-        self.misc_deaths.lava = misc_deaths_cursor.read_f64::<LE>()?;
-        misc_deaths_cursor.seek(Current(0x10))?;
+impl Stats {
+    /// Every counter with its value, in `Stats::KEYS` order.
+    pub fn iter(&self) -> impl Iterator<Item = (&'static StatKey, f64)> + '_ {
+        StatKey::ALL
+            .iter()
+            .map(|stat| (stat, self.get(stat.key).expect("Every key has a field")))
+    }
 
-        cursor.seek(Current(8 * 4))?;
+    fn category(&self, category: Category) -> impl Iterator<Item = (&'static StatKey, f64)> + '_ {
+        self.iter()
+            .filter(move |(stat, _)| stat.category == category)
+    }
 
-        // Enemy Kills
-        self.enemy_kills.bat = cursor.read_f64::<LE>()?;
-        let enemy_kills_list_offset =
-            read_uptr32_t(process, (offset as u64 + cursor.position() + 0x8) as usize)? as usize;
-        let enemy_kills_list_offset = read_uptr32_t(process, enemy_kills_list_offset)? as usize;
-        let mut enemy_kills_cursor = read_n_bytes(process, enemy_kills_list_offset + 0x20, 0x1e8)?;
+    pub fn pprint(&self) {
+        for (stat, value) in self.category(Category::General) {
+            println!("{:19}{}", format!("{}:", stat.name), value);
+        }
 
-        self.enemy_kills.snake = enemy_kills_cursor.read_f64::<LE>()?;
-        enemy_kills_cursor.seek(Current(0x10))?;
+        println!();
+        println!("Levels:");
+        let levels: Vec<_> = self.category(Category::LevelDeaths).collect();
+        for (area, row) in ["Mines", "Jungle", "Ice Caves", "Temple"]
+            .iter()
+            .zip(levels.chunks(4))
+        {
+            let row: Vec<_> = row
+                .iter()
+                .map(|(_, value)| format!("{:5}", value))
+                .collect();
+            println!("  {:11}{}", format!("{}:", area), row.join(" "));
+        }
+
+        println!();
+        println!("Misc:");
+        for (stat, value) in self.category(Category::MiscDeaths) {
+            println!("  {:14}{}", format!("{}:", stat.name), value);
+        }
 
-        self.enemy_kills.spider = enemy_kills_cursor.read_f64::<LE>()?;
-        enemy_kills_cursor.seek(Current(0x10))?;
+        println!();
+        println!("Enemies:       Deaths   Kills");
+        for enemy in Self::ENEMY_ORDER {
+            let stat = |collection: &str| {
+                let key = format!("{}.{}", collection, enemy);
+                StatKey::ALL.iter().find(|stat| stat.key == key)
+            };
+            let (deaths, kills) = (stat("enemy_deaths"), stat("enemy_kills"));
+            let name = deaths.or(kills).expect("Every enemy has a counter").name;
+            let count = |stat: Option<&StatKey>| {
+                stat.map(|stat| {
+                    self.get(stat.key)
+                        .expect("Every key has a field")
+                        .to_string()
+                })
+                .unwrap_or_default()
+            };
+            let row = format!(
+                "  {:14}{:>5}   {}",
+                format!("{}:", name),
+                count(deaths),
+                count(kills)
+            );
+            println!("{}", row.trim_end());
+        }
+    }
 
-        self.enemy_kills.giant_spider = enemy_kills_cursor.read_f64::<LE>()?;
-        enemy_kills_cursor.seek(Current(0x10))?;
+    /// Enemies in the order `pprint` lists them: the kill counters' order, with the
+    /// death-only enemies where they've always been printed.
+    const ENEMY_ORDER: &'static [&'static str] = &[
+        "bat",
+        "snake",
+        "spider",
+        "giant_spider",
+        "caveman",
+        "skeleton",
+        "zombie",
+        "vampire",
+        "frog",
+        "monkey",
+        "fire_frog",
+        "mantrap",
+        "piranha",
+        "megamouth",
+        "yeti",
+        "yeti_king",
+        "alien",
+        "ufo",
+        "alien_boss",
+        "hawkman",
+        "shopkeeper",
+        "tomb_lord",
+        "magma_man",
+        "olmec",
+        "ghost",
+    ];
+
+    /// Reject snapshots with counters no real save could have, which happens when
+    /// the pointer chain is read mid-update during level transitions.
+    pub fn validate(&self) -> Result<(), TrackerError> {
+        for key in Self::KEYS {
+            let value = self.get(key).expect("Every key has a field");
+            if !value.is_finite() || value < 0.0 || value.fract() != 0.0 || value > MAX_PLAUSIBLE {
+                return Err(TrackerError::ValueOutOfRange {
+                    field: key.to_string(),
+                    value,
+                });
+            }
+        }
+        Ok(())
+    }
 
-        self.enemy_kills.caveman = enemy_kills_cursor.read_f64::<LE>()?;
-        enemy_kills_cursor.seek(Current(0x10))?;
+    /// The counters that differ in `new`, with their values here and there.
+    pub fn delta(&self, new: &Stats) -> StatsDelta {
+        let changes = Self::KEYS
+            .iter()
+            .filter_map(|key| {
+                let old = self.get(key).expect("Every key has a field");
+                let new = new.get(key).expect("Every key has a field");
+                (old != new).then_some(StatChange { key, old, new })
+            })
+            .collect();
+        StatsDelta { changes }
+    }
 
-        self.enemy_kills.skeleton = enemy_kills_cursor.read_f64::<LE>()?;
-        enemy_kills_cursor.seek(Current(0x10))?;
+    /// Read the stats of the attached game.
+    pub fn from_memory(process: &GameProcess, layout: &StatsLayout) -> Result<Self, TrackerError> {
+        Self::read(process, process.base_addr(), layout)
+    }
 
-        self.enemy_kills.zombie = enemy_kills_cursor.read_f64::<LE>()?;
-        enemy_kills_cursor.seek(Current(0x10))?;
+    /// Read stats from any memory source with the game module at `base_addr`, e.g. a
+    /// recorded snapshot.
+    pub fn read<M: MemorySource + ?Sized>(
+        process: &M,
+        base_addr: usize,
+        layout: &StatsLayout,
+    ) -> Result<Self, TrackerError> {
+        let mut stats = Self::default();
+        stats.update_from_memory(process, base_addr, layout)?;
+        Ok(stats)
+    }
 
-        self.enemy_kills.vampire = enemy_kills_cursor.read_f64::<LE>()?;
-        enemy_kills_cursor.seek(Current(0x10))?;
+    /// Read stats until two consecutive samples agree, so a single snapshot can't mix
+    /// values from before and after the game updated them. Gives up after
+    /// `max_retries` samples beyond the first pair. Implausible samples are
+    /// rejected by `validate` right away, since NaNs would never agree.
+    pub fn from_memory_consistent<M: MemorySource + ?Sized>(
+        process: &M,
+        base_addr: usize,
+        layout: &StatsLayout,
+        max_retries: usize,
+    ) -> Result<Self, TrackerError> {
+        let sample = || -> Result<Self, TrackerError> {
+            let stats = Self::read(process, base_addr, layout)?;
+            stats.validate()?;
+            Ok(stats)
+        };
+
+        let mut previous = sample()?;
+        for _ in 0..=max_retries {
+            let current = sample()?;
+            if current == previous {
+                return Ok(current);
+            }
+            previous = current;
+        }
+
+        Err(TrackerError::InconsistentRead {
+            attempts: max_retries + 2,
+        })
+    }
 
-        self.enemy_kills.frog = enemy_kills_cursor.read_f64::<LE>()?;
-        enemy_kills_cursor.seek(Current(0x10))?;
+    pub fn update_from_memory<M: MemorySource + ?Sized>(
+        &mut self,
+        process: &M,
+        base_addr: usize,
+        layout: &StatsLayout,
+    ) -> Result<(), TrackerError> {
+        layout.read_stats(process, base_addr, self)
+    }
+}
 
-        self.enemy_kills.fire_frog = enemy_kills_cursor.read_f64::<LE>()?;
-        enemy_kills_cursor.seek(Current(0x10))?;
+/// Lay `stats` out in a fake game the way the baseline tracker read them: the
+/// stat block behind `[0x0018f124, 0x4]` plus 0xd58, in 0x28-byte slots, with list
+/// stats reached through the pointer at +0x10 and elements at +0x20 every 0x18 bytes.
+/// Returns the memory and the module base.
+#[cfg(test)]
+pub(crate) fn fake_game(stats: &Stats) -> (crate::mem_reader::FakeMemory, usize) {
+    let base_addr = 0x400000;
+    let block_addr = 0x0200_0000 + 0xd58;
+    let mut memory = crate::mem_reader::FakeMemory::default();
+    memory.write(base_addr, &crate::game_build::fake_header(&[]));
+    memory.write_u32(base_addr + 0x0018f124, 0x0100_0000);
+    memory.write_u32(0x0100_0000 + 0x4, 0x0200_0000);
+    // Make every slot readable, like the real block.
+    memory.write(block_addr, &[0; 0x28 * 20]);
+
+    let mut lists = Vec::new();
+
+    for field in &StatsLayout::builtin().fields {
+        let value = stats.get(&field.key).expect("Layout keys are stats");
+        let slot_addr = block_addr + field.slot * 0x28;
+        if field.index == 0 {
+            memory.write_f64(slot_addr, value);
+            continue;
+        }
+        let list_pointer = 0x0300_0000 + field.slot * 0x100;
+        let list_addr = 0x0400_0000 + field.slot * 0x1000;
+        if !lists.contains(&field.slot) {
+            lists.push(field.slot);
+            memory.write_u32(slot_addr + 0x10, list_pointer as u32);
+            memory.write_u32(list_pointer, list_addr as u32);
+            memory.write(list_addr, &[0; 0x20 + 0x18 * 32]);
+        }
+        memory.write_f64(list_addr + 0x20 + (field.index - 1) * 0x18, value);
+    }
+    (memory, base_addr)
+}
 
-        self.enemy_kills.mantrap = enemy_kills_cursor.read_f64::<LE>()?;
-        enemy_kills_cursor.seek(Current(0x10))?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stats with a different value in every counter.
+    fn numbered_stats() -> Stats {
+        let mut stats = Stats::default();
+        for (n, key) in Stats::KEYS.iter().enumerate() {
+            *stats.field_mut(key).unwrap() = (n + 1) as f64;
+        }
+        stats
+    }
 
-        self.enemy_kills.piranha = enemy_kills_cursor.read_f64::<LE>()?;
-        enemy_kills_cursor.seek(Current(0x10))?;
+    #[test]
+    fn builtin_layout_matches_baseline_offsets() {
+        let layout = StatsLayout::builtin();
+        assert_eq!(layout.slot_stride, 0x28);
+        assert_eq!(layout.list_pointer_offset, 0x10);
+        assert_eq!(layout.list_base, 0x20);
+        assert_eq!(layout.list_stride, 0x18);
+
+        let stats = numbered_stats();
+        let (memory, base_addr) = fake_game(&stats);
+        assert_eq!(Stats::read(&memory, base_addr, layout).unwrap(), stats);
+    }
 
-        self.enemy_kills.megamouth = enemy_kills_cursor.read_f64::<LE>()?;
-        enemy_kills_cursor.seek(Current(0x10))?;
+    #[test]
+    fn list_elements_follow_slot_value() {
+        let mut stats = Stats::default();
+        stats.level_deaths.level_1 = 3.0;
+        stats.level_deaths.level_2 = 5.0;
+        stats.enemy_kills.olmec = 7.0;
+        stats.enemy_kills.monkey = 11.0;
+        let (memory, base_addr) = fake_game(&stats);
+
+        let read = Stats::read(&memory, base_addr, StatsLayout::builtin()).unwrap();
+        assert_eq!(read.level_deaths.level_1, 3.0);
+        assert_eq!(read.level_deaths.level_2, 5.0);
+        assert_eq!(read.enemy_kills.olmec, 7.0);
+        assert_eq!(read.enemy_kills.monkey, 11.0);
+    }
 
-        self.enemy_kills.yeti = enemy_kills_cursor.read_f64::<LE>()?;
-        enemy_kills_cursor.seek(Current(0x10))?;
+    /// Serves a different fake game for every sample of the stats, switching when
+    /// the root pointer is read.
+    struct Changing {
+        frames: Vec<crate::mem_reader::FakeMemory>,
+        root_addr: usize,
+        sample: std::cell::Cell<usize>,
+    }
 
-        self.enemy_kills.yeti_king = enemy_kills_cursor.read_f64::<LE>()?;
-        enemy_kills_cursor.seek(Current(0x10))?;
+    impl Changing {
+        fn new(frames: &[Stats]) -> (Self, usize) {
+            let mut base_addr = 0;
+            let frames = frames
+                .iter()
+                .map(|stats| {
+                    let (memory, base) = fake_game(stats);
+                    base_addr = base;
+                    memory
+                })
+                .collect();
+            let changing = Changing {
+                frames,
+                root_addr: base_addr + StatsLayout::builtin().root[0],
+                sample: std::cell::Cell::new(0),
+            };
+            (changing, base_addr)
+        }
+    }
 
-        self.enemy_kills.alien = enemy_kills_cursor.read_f64::<LE>()?;
-        enemy_kills_cursor.seek(Current(0x10))?;
+    impl MemorySource for Changing {
+        fn read_n_bytes(
+            &self,
+            addr: usize,
+            num_bytes: usize,
+        ) -> Result<std::io::Cursor<Vec<u8>>, TrackerError> {
+            if addr == self.root_addr {
+                self.sample.set(self.sample.get() + 1);
+            }
+            let frame = self
+                .sample
+                .get()
+                .saturating_sub(1)
+                .min(self.frames.len() - 1);
+            self.frames[frame].read_n_bytes(addr, num_bytes)
+        }
+    }
 
-        self.enemy_kills.ufo = enemy_kills_cursor.read_f64::<LE>()?;
-        enemy_kills_cursor.seek(Current(0x10))?;
+    fn crates(crates_opened: f64) -> Stats {
+        Stats {
+            crates_opened,
+            ..Default::default()
+        }
+    }
 
-        self.enemy_kills.alien_boss = enemy_kills_cursor.read_f64::<LE>()?;
-        enemy_kills_cursor.seek(Current(0x10))?;
+    #[test]
+    fn consistent_read_waits_for_samples_to_agree() {
+        let (memory, base_addr) = Changing::new(&[crates(1.0), crates(2.0), crates(2.0)]);
+        let stats =
+            Stats::from_memory_consistent(&memory, base_addr, StatsLayout::builtin(), 3).unwrap();
+        assert_eq!(stats, crates(2.0));
+        assert_eq!(memory.sample.get(), 3);
+    }
 
-        self.enemy_kills.hawkman = enemy_kills_cursor.read_f64::<LE>()?;
-        enemy_kills_cursor.seek(Current(0x10))?;
+    #[test]
+    fn consistent_read_gives_up_after_retries() {
+        let frames: Vec<_> = (0..10).map(|n| crates(n as f64)).collect();
+        let (memory, base_addr) = Changing::new(&frames);
+        let err = Stats::from_memory_consistent(&memory, base_addr, StatsLayout::builtin(), 2)
+            .unwrap_err();
+        assert_eq!(err, TrackerError::InconsistentRead { attempts: 4 });
+        assert_eq!(memory.sample.get(), 4);
+    }
 
-        self.enemy_kills.shopkeeper = enemy_kills_cursor.read_f64::<LE>()?;
-        enemy_kills_cursor.seek(Current(0x10))?;
+    #[test]
+    fn consistent_read_names_nan_fields() {
+        let (memory, base_addr) = Changing::new(&[crates(f64::NAN)]);
+        let err = Stats::from_memory_consistent(&memory, base_addr, StatsLayout::builtin(), 2)
+            .unwrap_err();
+        assert!(
+            matches!(err, TrackerError::ValueOutOfRange { ref field, .. } if field == "crates_opened")
+        );
+    }
 
-        self.enemy_kills.tomb_lord = enemy_kills_cursor.read_f64::<LE>()?;
-        enemy_kills_cursor.seek(Current(0x10))?;
+    #[test]
+    fn delta_lists_changed_counters_in_key_order() {
+        let mut old = crates(3.0);
+        old.enemy_kills.monkey = 1.0;
+        old.enemy_deaths.spider = 5.0;
+        let mut new = old.clone();
+        // Changed out of key order, and one counter set to the value it already had.
+        new.enemy_kills.monkey = 2.0;
+        new.crates_opened = 4.0;
+        new.enemy_deaths.spider = 5.0;
+
+        let change = |key, old, new| StatChange { key, old, new };
+        assert_eq!(
+            old.delta(&new).changes,
+            [
+                change("crates_opened", 3.0, 4.0),
+                change("enemy_kills.monkey", 1.0, 2.0),
+            ]
+        );
+        assert!(new.delta(&new).changes.is_empty());
 
-        self.enemy_kills.olmec = enemy_kills_cursor.read_f64::<LE>()?;
-        enemy_kills_cursor.seek(Current(0x10))?;
+        let every = Stats::default().delta(&numbered_stats());
+        let keys: Vec<_> = every.changes.iter().map(|change| change.key).collect();
+        assert_eq!(keys, Stats::KEYS);
+    }
 
-        cursor.seek(Current(8 * 4))?;
+    #[test]
+    fn pprint_lists_every_enemy_once() {
+        let mut listed: Vec<_> = Stats::ENEMY_ORDER.to_vec();
+        listed.sort_unstable();
+        listed.dedup();
+        assert_eq!(listed.len(), Stats::ENEMY_ORDER.len());
+
+        let mut enemies: Vec<_> = StatKey::ALL
+            .iter()
+            .filter(|stat| matches!(stat.category, Category::EnemyDeaths | Category::EnemyKills))
+            .map(|stat| stat.key.split('.').nth(1).unwrap())
+            .collect();
+        enemies.sort_unstable();
+        enemies.dedup();
+        assert_eq!(listed, enemies);
+    }
 
-        self.enemy_kills.monkey = cursor.read_f64::<LE>()?;
+    #[test]
+    fn every_stat_has_an_icon() {
+        let icons = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("frontend/public/images/stats-icons");
+        for stat in StatKey::ALL {
+            let icon = icons.join(format!("{}.png", stat.icon));
+            assert!(
+                icon.is_file(),
+                "{} has no icon at {}",
+                stat.key,
+                icon.display()
+            );
+        }
+    }
 
-        Ok(())
+    #[test]
+    fn missing_stat_block_is_an_error() {
+        let memory = crate::mem_reader::FakeMemory::default();
+        let err = Stats::read(&memory, 0x400000, StatsLayout::builtin()).unwrap_err();
+        assert!(matches!(err, TrackerError::ReadFailed { .. }));
     }
 }